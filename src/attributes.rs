@@ -0,0 +1,82 @@
+use std::{fs, io::ErrorKind, path::Path};
+
+use crate::{ignore::glob_match, Config, Result};
+
+pub const ATTRIBUTES_FILE: &str = ".dircacheattributes";
+
+/// The lines of a `.dircacheattributes` file: a pattern, matched like those
+/// of `.dircacheignore`, followed by attributes. `binary`, `-diff` and
+/// `-text` mark matching files as binary, `diff` and `text` as text. The
+/// last matching line wins.
+#[derive(Debug, Default)]
+pub struct Attributes {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    glob: String,
+    anchored: bool,
+    binary: bool,
+}
+
+impl Attributes {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        match fs::read_to_string(config.work_dir.join(ATTRIBUTES_FILE)) {
+            Ok(content) => Ok(Self::parse(&content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let mut rules = Vec::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let pattern = match fields.next() {
+                Some(pattern) => pattern,
+                None => continue,
+            };
+            for attribute in fields {
+                let binary = match attribute {
+                    "binary" | "-diff" | "-text" => true,
+                    "diff" | "text" => false,
+                    _ => continue,
+                };
+                rules.push(Rule {
+                    glob: pattern.trim_start_matches('/').to_owned(),
+                    anchored: pattern.contains('/'),
+                    binary,
+                });
+            }
+        }
+
+        Self { rules }
+    }
+
+    /// Whether `path`, relative to the top of the work tree, is marked as
+    /// binary or text, if any line matches it.
+    pub fn is_binary(&self, path: &Path) -> Option<bool> {
+        let full_path = path.to_string_lossy();
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                let text = if rule.anchored {
+                    &full_path
+                } else {
+                    &file_name
+                };
+                glob_match(rule.glob.as_bytes(), text.as_bytes())
+            })
+            .map(|rule| rule.binary)
+    }
+}
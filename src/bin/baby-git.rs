@@ -1,14 +1,44 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{stderr, stdin, stdout, Cursor, Read},
+    path::PathBuf,
+    process, thread,
+    time::Duration,
+};
+
 use clap::Clap;
 use tracing::debug;
+use tracing_subscriber::EnvFilter;
 
-use opt::{Opt, SubCommand};
+use opt::{
+    BisectCommand, BundleCommand, Opt, ReflogCommand, StashCommand, SubCommand, SubmoduleCommand,
+};
 
 use baby_git_rs::{
-    cat_file, commit_tree, init_db, read_tree, show_diff, update_cache, write_tree, Config, Result,
+    am, apply, bisect_check, bisect_mark, bisect_reset, bisect_start, blame, bundle_create,
+    bundle_unbundle, bundle_verify, cat_file, cat_file_batch, cat_file_info, cat_file_to,
+    checkout_index, cherry_pick, cherry_pick_abort, cherry_pick_continue, clone, commit,
+    commit_tree,
+    diff::{format_stats, parse_patch, tree_diff_stats, FileStat, RenameOptions, StatFormat},
+    diff_tree, expand_paths, fetch, fetch_pack, format_blame, format_patch, hash_object, init_db,
+    is_bundle, log, ls_files, ls_tree, prune, push, read_tree, rebase, rebase_abort,
+    rebase_continue, rebase_skip, receive_pack, reflog_expire, reflog_show, refs, reset,
+    reset_paths, revert,
+    revision::parse_revision,
+    send_pack, show_diff, show_diff_stats, split_ident, split_mbox, stash_apply, stash_drop,
+    stash_list, stash_push, stash_show, submodule_init, submodule_update, switch,
+    transport::Connection,
+    update_cache, update_ref, update_tracked, upload_pack, write_tree, ApplyTarget, BisectStep,
+    BisectTerm, Bundle, Config, GitError, GitObject, LogOptions, LsFilesFilter, PickOutcome,
+    RebaseOutcome, ResetMode, Result,
 };
 
 fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(stderr)
+        .init();
 
     let config = Config::from_env()?;
     debug!(?config);
@@ -21,7 +51,27 @@ fn main() -> Result<()> {
             init_db(&config)?;
             println!("defaulting to private storage area");
         }
-        SubCommand::UpdateCache { paths } => update_cache(paths, &config)?,
+        SubCommand::UpdateCache {
+            recursive,
+            jobs,
+            progress,
+            paths,
+        } => {
+            let paths = if recursive {
+                expand_paths(paths, &config)?
+            } else {
+                paths
+            };
+            update_cache(paths, default_jobs(jobs)?, progress, &config)?
+        }
+        SubCommand::Add {
+            jobs,
+            progress,
+            paths,
+        } => {
+            let paths = expand_paths(paths, &config)?;
+            update_cache(paths, default_jobs(jobs)?, progress, &config)?
+        }
         SubCommand::WriteTree => {
             let sha1 = write_tree(&config)?;
             println!("{}", sha1);
@@ -34,28 +84,729 @@ fn main() -> Result<()> {
             let sha1 = commit_tree(tree_hash, parents, &config)?;
             println!("{}", sha1);
         }
+        SubCommand::Commit {
+            all,
+            message,
+            file,
+            allow_empty,
+            amend,
+        } => {
+            if all {
+                update_tracked(default_jobs(None)?, &config)?;
+            }
+
+            let message = match file {
+                Some(file) if file.as_os_str() == "-" => {
+                    let mut buf = String::new();
+                    let _ = stdin().read_to_string(&mut buf)?;
+                    Some(buf)
+                }
+                Some(file) => Some(fs::read_to_string(file)?),
+                None => message,
+            };
+            let sha1 = commit(message, allow_empty, amend, &config)?;
+            println!("{}", sha1);
+        }
+        SubCommand::Reset {
+            soft,
+            mixed,
+            hard,
+            args,
+        } => {
+            let (rev, paths) = match args.split_first() {
+                Some((first, rest)) if parse_revision(first, &config).is_ok() => {
+                    (first.as_str(), rest)
+                }
+                _ => ("HEAD", &args[..]),
+            };
+            let commit_hash = parse_revision(rev, &config)?;
+            let mode = match (soft, mixed, hard) {
+                (true, _, _) => ResetMode::Soft,
+                (_, _, true) => ResetMode::Hard,
+                _ => ResetMode::Mixed,
+            };
+
+            if !paths.is_empty() {
+                if mode != ResetMode::Mixed {
+                    eprintln!("error: cannot do a soft or hard reset with paths");
+                    process::exit(128);
+                }
+                let paths = paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+                reset_paths(&commit_hash, &paths, &config)?;
+            } else {
+                reset(&commit_hash, rev, mode, &config)?;
+            }
+        }
+        SubCommand::CherryPick {
+            continue_,
+            abort,
+            allow_empty,
+            commit,
+        } => pick(false, continue_, abort, allow_empty, commit, &config)?,
+        SubCommand::Revert {
+            continue_,
+            abort,
+            allow_empty,
+            commit,
+        } => pick(true, continue_, abort, allow_empty, commit, &config)?,
+        SubCommand::Rebase {
+            continue_,
+            skip,
+            abort,
+            todo,
+            upstream,
+        } => {
+            let outcome = if continue_ {
+                rebase_continue(&config)?
+            } else if skip {
+                rebase_skip(&config)?
+            } else if abort {
+                rebase_abort(&config)?;
+                return Ok(());
+            } else {
+                rebase(&upstream.unwrap_or_default(), todo.as_deref(), &config)?
+            };
+            match outcome {
+                RebaseOutcome::Finished(sha1) => println!("{}", sha1),
+                RebaseOutcome::Stopped(conflicts) => {
+                    report_conflicts(&conflicts);
+                    eprintln!(
+                        "hint: after resolving the conflicts, add them and run with --continue, \
+                         or use --skip or --abort"
+                    );
+                    process::exit(1);
+                }
+            }
+        }
+        SubCommand::Blame {
+            porcelain,
+            path,
+            rev,
+        } => {
+            let commit_hash = parse_revision(&rev, &config)?;
+            let lines = blame(&path, &commit_hash, &config)?;
+            for line in format_blame(&lines, &path, porcelain, &config)? {
+                println!("{}", line);
+            }
+        }
+        SubCommand::DiffTree {
+            renames,
+            copies,
+            threshold,
+            limit,
+            stat,
+            numstat,
+            shortstat,
+            old,
+            new,
+        } => {
+            let (old, new) = match new {
+                Some(new) => (
+                    Some(parse_revision(&old, &config)?),
+                    parse_revision(&new, &config)?,
+                ),
+                None => {
+                    let commit_hash = parse_revision(&old, &config)?;
+                    let commit_obj = GitObject::read_commit(&config.obj_db_path, &commit_hash)?;
+                    (commit_obj.parents().first().cloned(), commit_hash)
+                }
+            };
+            let options = RenameOptions {
+                renames,
+                copies,
+                threshold,
+                limit,
+            };
+            let renames = if renames || copies {
+                Some(&options)
+            } else {
+                None
+            };
+            let changes = diff_tree(old.as_ref(), &new, renames, &config)?;
+            match stat_format(stat, numstat, shortstat) {
+                Some(format) => print_stats(&tree_diff_stats(&changes, &config)?, format),
+                None => {
+                    for change in changes {
+                        println!("{}", change);
+                    }
+                }
+            }
+        }
+        SubCommand::Log {
+            follow,
+            renames,
+            name_status,
+            stat,
+            numstat,
+            shortstat,
+            max_count,
+            rev,
+            path,
+        } => {
+            let commit_hash = parse_revision(&rev, &config)?;
+            let format = stat_format(stat, numstat, shortstat);
+            let options = LogOptions {
+                path,
+                follow,
+                changes: name_status || format.is_some(),
+                renames,
+                max_count,
+            };
+            for (i, entry) in log(&commit_hash, &options, &config)?.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                let (name, email, date) = split_ident(entry.commit.author_info());
+                println!("commit {}", entry.sha1);
+                println!("Author: {} <{}>", name, email);
+                println!("Date:   {}", date);
+                println!();
+                for line in entry.commit.user_comment().lines() {
+                    println!("    {}", line);
+                }
+                if name_status {
+                    println!();
+                    for change in &entry.changes {
+                        println!("{}", change);
+                    }
+                }
+                if let Some(format) = format {
+                    println!();
+                    print_stats(&tree_diff_stats(&entry.changes, &config)?, format);
+                }
+            }
+        }
+        SubCommand::FormatPatch {
+            output_directory,
+            range,
+        } => {
+            let (upstream, head) = match range.split_once("..") {
+                Some((upstream, head)) => (upstream, if head.is_empty() { "HEAD" } else { head }),
+                None => (range.as_str(), "HEAD"),
+            };
+            let upstream = parse_revision(upstream, &config)?;
+            let head = parse_revision(head, &config)?;
+            for path in format_patch(&upstream, &head, &output_directory, &config)? {
+                println!("{}", path.display());
+            }
+        }
+        SubCommand::Apply {
+            check,
+            index,
+            cached,
+            patches,
+        } => {
+            let target = if cached {
+                ApplyTarget::Cached
+            } else if index {
+                ApplyTarget::Index
+            } else {
+                ApplyTarget::WorkTree
+            };
+            let patches = parse_patch(&read_inputs(&patches)?)?;
+            let _ = apply(&patches, target, check, &config)?;
+        }
+        SubCommand::Am { mboxes } => {
+            let mails = split_mbox(&read_inputs(&mboxes)?, &config)?;
+            for (i, mail) in mails.iter().enumerate() {
+                println!("Applying: {}", mail.subject());
+                if let Err(e) = am(mail, &config) {
+                    eprintln!("Patch failed at {:04} {}", i + 1, mail.subject());
+                    return Err(e);
+                }
+            }
+        }
+        SubCommand::Switch {
+            create,
+            detach,
+            rev,
+        } => switch(&rev, create.as_deref(), detach, &config)?,
         SubCommand::ReadTree { tree_hash } => {
             let entries = read_tree(tree_hash, &config)?;
             for entry in entries {
                 println!("{}", entry);
             }
         }
-        SubCommand::ShowDiff => show_diff(&config)?,
-        SubCommand::CatFile { object_hash } => {
-            let (tmp_path, obj_type) = cat_file(object_hash, &config)?;
-            println!("{:?}: {}", tmp_path, obj_type);
+        SubCommand::ShowDiff {
+            stat,
+            numstat,
+            shortstat,
+        } => match stat_format(stat, numstat, shortstat) {
+            Some(format) => print_stats(&show_diff_stats(&config)?, format),
+            None => show_diff(&config)?,
+        },
+        SubCommand::CatFile {
+            show_type,
+            show_size,
+            exists,
+            pretty,
+            batch,
+            batch_check,
+            args,
+        } => {
+            if batch || batch_check {
+                let stdin = stdin();
+                return cat_file_batch(stdin.lock(), stdout(), batch, &config);
+            }
+
+            let (expected_type, object) = match &args[..] {
+                [object] => (None, object),
+                [obj_type, object] => (Some(obj_type.as_str()), object),
+                _ => unreachable!("clap requires one or two arguments"),
+            };
+            if exists {
+                let found = parse_revision(object, &config)
+                    .map(|sha1| GitObject::has_sha1_file(&config.obj_db_path, &sha1))
+                    .unwrap_or(false);
+                process::exit(if found { 0 } else { 1 });
+            }
+
+            let object_hash = parse_revision(object, &config)?;
+            if show_type || show_size {
+                let (obj_type, size) = cat_file_info(&object_hash, &config)?;
+                if show_type {
+                    println!("{}", obj_type);
+                } else {
+                    println!("{}", size);
+                }
+            } else if pretty || expected_type.is_some() {
+                let _ = cat_file_to(&object_hash, expected_type, stdout().lock(), &config)?;
+            } else {
+                let (tmp_path, obj_type) = cat_file(object_hash, &config)?;
+                println!("{:?}: {}", tmp_path, obj_type);
+            }
+        }
+        SubCommand::HashObject {
+            write,
+            stdin: read_stdin,
+            obj_type,
+            paths,
+        } => {
+            if read_stdin {
+                let input = read_inputs(&[])?;
+                let sha1 = hash_object(Cursor::new(input), None, &obj_type, write, &config)?;
+                println!("{}", sha1);
+            }
+            for path in paths {
+                let file = File::open(&path)?;
+                let size = file.metadata()?.len();
+                let sha1 = hash_object(file, Some(size), &obj_type, write, &config)?;
+                println!("{}", sha1);
+            }
+        }
+        SubCommand::LsFiles {
+            cached,
+            deleted,
+            modified,
+            others,
+            stage,
+            zero_terminated,
+        } => {
+            let filter = LsFilesFilter {
+                cached,
+                deleted,
+                modified,
+                others,
+            };
+            let terminator = if zero_terminated { '\0' } else { '\n' };
+            for line in ls_files(filter, stage, &config)? {
+                print!("{}{}", line, terminator);
+            }
+        }
+        SubCommand::LsTree {
+            recursive,
+            show_trees,
+            name_only,
+            tree_ish,
+            path,
+        } => {
+            let tree_hash = parse_revision(&tree_ish, &config)?;
+            let listing = ls_tree(&tree_hash, path.as_deref(), recursive, show_trees, &config)?;
+            if name_only {
+                for path in listing.entries.keys() {
+                    println!("{}", path.display());
+                }
+            } else {
+                print!("{}", listing);
+            }
+        }
+        SubCommand::CheckoutIndex { force, paths } => {
+            for path in checkout_index(paths, force, &config)? {
+                println!("{}", path.display());
+            }
+        }
+        SubCommand::UpdateRef { message, name, rev } => update_ref(&name, &rev, &message, &config)?,
+        SubCommand::UploadPack { directory } => {
+            let config = Config::with_work_dir(&directory)?;
+            upload_pack(stdin().lock(), stdout().lock(), &config)?;
+        }
+        SubCommand::ReceivePack { directory } => {
+            let config = Config::with_work_dir(&directory)?;
+            receive_pack(stdin().lock(), stdout().lock(), &config)?;
+        }
+        SubCommand::FetchPack { exec, remote, refs } => {
+            let mut conn = Connection::spawn(&exec_path(exec)?, "upload-pack", &remote)?;
+            let fetched = fetch_pack(&refs, &mut conn.reader, &mut conn.writer, &config)?;
+            conn.finish()?;
+
+            for (name, sha1) in fetched {
+                println!("{} {}", sha1, name);
+            }
+        }
+        SubCommand::Fetch {
+            force,
+            exec,
+            remote,
+            specs,
+        } => {
+            let fetched = if is_bundle(&remote) {
+                bundle_unbundle(&remote, &config)?
+            } else {
+                let names = specs
+                    .iter()
+                    .filter_map(|spec| spec.src.clone())
+                    .collect::<Vec<_>>();
+                let mut conn = Connection::spawn(&exec_path(exec)?, "upload-pack", &remote)?;
+                let fetched = fetch_pack(&names, &mut conn.reader, &mut conn.writer, &config)?;
+                conn.finish()?;
+                fetched
+            };
+
+            for line in fetch(&fetched, &specs, force, &config)? {
+                println!("{}", line);
+            }
+        }
+        SubCommand::Clone {
+            exec,
+            remote,
+            directory,
+        } => {
+            println!("Cloning into '{}'...", directory.display());
+            fs::create_dir_all(&directory)?;
+            let config = Config::with_work_dir(&directory)?;
+            init_db(&config)?;
+
+            let fetched = if is_bundle(&remote) {
+                bundle_unbundle(&remote, &config)?
+            } else {
+                let mut conn = Connection::spawn(&exec_path(exec)?, "upload-pack", &remote)?;
+                let fetched = fetch_pack(&[], &mut conn.reader, &mut conn.writer, &config)?;
+                conn.finish()?;
+                fetched
+            };
+            if clone(&fetched, &config)?.is_none() {
+                eprintln!("warning: remote HEAD refers to nonexistent ref, unable to checkout");
+            }
+        }
+        SubCommand::Bundle { cmd } => match cmd {
+            BundleCommand::Create { all, file, revs } => {
+                let mut revs = revs;
+                if all {
+                    revs.push(refs::HEAD.to_owned());
+                    revs.extend(refs::list_refs(&config)?.into_iter().map(|(name, _)| name));
+                }
+                let bundle = bundle_create(&file, &revs, &config)?;
+                report_bundle(&bundle);
+            }
+            BundleCommand::Verify { file } => {
+                let bundle = bundle_verify(&file, &config)?;
+                report_bundle(&bundle);
+                println!("{} is okay", file.display());
+            }
+            BundleCommand::Unbundle { file } => {
+                for (name, sha1) in bundle_unbundle(&file, &config)? {
+                    println!("{} {}", sha1, name);
+                }
+            }
+        },
+        SubCommand::SendPack { exec, remote, refs } => {
+            let mut conn = Connection::spawn(&exec_path(exec)?, "receive-pack", &remote)?;
+            let report = send_pack(&refs, &mut conn.reader, &mut conn.writer, &config)?;
+            conn.finish()?;
+            print_ref_report(&report);
+        }
+        SubCommand::Push {
+            force,
+            exec,
+            remote,
+            specs,
+        } => {
+            let mut conn = Connection::spawn(&exec_path(exec)?, "receive-pack", &remote)?;
+            let report = push(specs, force, &mut conn.reader, &mut conn.writer, &config)?;
+            conn.finish()?;
+            print_ref_report(&report);
+        }
+        SubCommand::Prune {
+            dry_run,
+            grace_days,
+        } => {
+            let grace_period = Duration::from_secs(grace_days * 24 * 60 * 60);
+            let report = prune(grace_period, dry_run, &config)?;
+            for sha1 in &report.pruned {
+                println!("{}", sha1);
+            }
+            println!(
+                "{} {} objects, reclaimed {} bytes",
+                if dry_run { "would prune" } else { "pruned" },
+                report.pruned.len(),
+                report.reclaimed
+            );
+        }
+        SubCommand::Reflog { cmd } => match cmd {
+            ReflogCommand::Show { name } => {
+                let (name, entries) = reflog_show(name.as_deref(), &config)?;
+                for (i, entry) in entries.iter().enumerate() {
+                    println!("{} {}@{{{}}}: {}", entry.new, name, i, entry.message);
+                }
+            }
+            ReflogCommand::Expire { expire_days, names } => {
+                let expire = chrono::Duration::days(expire_days);
+                let expired = reflog_expire(&names, expire, &config)?;
+                println!("expired {} reflog entries", expired);
+            }
+        },
+        SubCommand::Stash { cmd } => match cmd.unwrap_or(StashCommand::Push { message: None }) {
+            StashCommand::Push { message } => {
+                let sha1 = stash_push(message.as_deref(), &config)?;
+                println!("Saved working directory and index state {}", sha1);
+            }
+            StashCommand::List => {
+                for (i, entry) in stash_list(&config)?.iter().enumerate() {
+                    println!("stash@{{{}}}: {}", i, entry.message);
+                }
+            }
+            StashCommand::Show { stash } => {
+                for (status, path) in stash_show(stash_index(stash)?, &config)? {
+                    println!("{}\t{}", status, path.display());
+                }
+            }
+            StashCommand::Apply { stash } => {
+                let conflicts = stash_apply(stash_index(stash)?, &config)?;
+                report_conflicts(&conflicts);
+            }
+            StashCommand::Pop { stash } => {
+                let n = stash_index(stash)?;
+                let conflicts = stash_apply(n, &config)?;
+                report_conflicts(&conflicts);
+                if conflicts.is_empty() {
+                    let sha1 = stash_drop(n, &config)?;
+                    println!("Dropped stash@{{{}}} ({})", n, sha1);
+                }
+            }
+            StashCommand::Drop { stash } => {
+                let n = stash_index(stash)?;
+                let sha1 = stash_drop(n, &config)?;
+                println!("Dropped stash@{{{}}} ({})", n, sha1);
+            }
+        },
+        SubCommand::Bisect { cmd } => match cmd {
+            BisectCommand::Start { bad, good } => {
+                let bad = bad.map(|rev| parse_revision(&rev, &config)).transpose()?;
+                let good = good
+                    .iter()
+                    .map(|rev| parse_revision(rev, &config))
+                    .collect::<Result<Vec<_>>>()?;
+                report_bisect(&bisect_start(bad.as_ref(), &good, &config)?);
+            }
+            BisectCommand::Good { rev } => {
+                let commit_hash = parse_revision(&rev, &config)?;
+                report_bisect(&bisect_mark(BisectTerm::Good, &commit_hash, &config)?);
+            }
+            BisectCommand::Bad { rev } => {
+                let commit_hash = parse_revision(&rev, &config)?;
+                report_bisect(&bisect_mark(BisectTerm::Bad, &commit_hash, &config)?);
+            }
+            BisectCommand::Skip { rev } => {
+                let commit_hash = parse_revision(&rev, &config)?;
+                report_bisect(&bisect_mark(BisectTerm::Skip, &commit_hash, &config)?);
+            }
+            BisectCommand::Reset => bisect_reset(&config)?,
+            BisectCommand::Run { cmd } => loop {
+                let term = bisect_check(&cmd, &config)?;
+                let commit_hash = parse_revision("HEAD", &config)?;
+                let step = bisect_mark(term, &commit_hash, &config)?;
+                report_bisect(&step);
+                if !matches!(step, BisectStep::Candidate { .. }) {
+                    break;
+                }
+            },
+        },
+        SubCommand::Submodule { cmd } => match cmd {
+            SubmoduleCommand::Init => {
+                for path in submodule_init(&config)? {
+                    println!("initialized {}", path.display());
+                }
+            }
+            SubmoduleCommand::Update { exec } => {
+                for (path, commit) in submodule_update(&exec_path(exec)?, &config)? {
+                    println!("{} {}", commit, path.display());
+                }
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn default_jobs(jobs: Option<usize>) -> Result<usize> {
+    match jobs {
+        Some(jobs) => Ok(jobs),
+        None => Ok(thread::available_parallelism()?.get()),
+    }
+}
+
+/// Parses `stash@{N}` or `N`, defaulting to the newest stash.
+fn stash_index(stash: Option<String>) -> Result<usize> {
+    let stash = match stash {
+        Some(stash) => stash,
+        None => return Ok(0),
+    };
+
+    stash
+        .strip_prefix("stash@{")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .unwrap_or(&stash)
+        .parse()
+        .map_err(|_| GitError::UnknownRevision(stash.clone()))
+}
+
+fn pick(
+    revert_commit: bool,
+    continue_: bool,
+    abort: bool,
+    allow_empty: bool,
+    commit: Option<String>,
+    config: &Config,
+) -> Result<()> {
+    if continue_ {
+        println!("{}", cherry_pick_continue(config)?);
+        return Ok(());
+    }
+    if abort {
+        return cherry_pick_abort(config);
+    }
+
+    let commit_hash = parse_revision(&commit.unwrap_or_default(), config)?;
+    let outcome = if revert_commit {
+        revert(&commit_hash, allow_empty, config)?
+    } else {
+        cherry_pick(&commit_hash, allow_empty, config)?
+    };
+    match outcome {
+        PickOutcome::Committed(sha1) => println!("{}", sha1),
+        PickOutcome::Conflicts(conflicts) => {
+            report_conflicts(&conflicts);
+            eprintln!("hint: after resolving the conflicts, add them and run with --continue");
+            process::exit(1);
         }
     }
 
     Ok(())
 }
 
+/// Prints the status of each ref update, exiting with an error if any was
+/// rejected.
+fn print_ref_report(report: &[String]) {
+    for line in report {
+        println!("{}", line);
+    }
+    if report.iter().any(|line| line.starts_with("ng ")) {
+        process::exit(1);
+    }
+}
+
+fn report_bisect(step: &BisectStep) {
+    match step {
+        BisectStep::Waiting => println!("waiting for both good and bad commits"),
+        BisectStep::Candidate {
+            commit,
+            subject,
+            remaining,
+        } => {
+            let steps = usize::BITS - remaining.leading_zeros();
+            println!(
+                "Bisecting: {} revisions left to test after this (roughly {} steps)",
+                remaining, steps
+            );
+            println!("[{}] {}", commit, subject);
+        }
+        BisectStep::Found(commit) => println!("{} is the first bad commit", commit),
+        BisectStep::OnlySkipped(commits) => {
+            println!("There are only 'skip'ped commits left to test.");
+            println!("The first bad commit could be any of:");
+            for commit in commits {
+                println!("{}", commit);
+            }
+        }
+    }
+}
+
+fn stat_format(stat: bool, numstat: bool, shortstat: bool) -> Option<StatFormat> {
+    match (stat, numstat, shortstat) {
+        (true, _, _) => Some(StatFormat::Stat),
+        (_, true, _) => Some(StatFormat::Numstat),
+        (_, _, true) => Some(StatFormat::Shortstat),
+        _ => None,
+    }
+}
+
+fn print_stats(stats: &[FileStat], format: StatFormat) {
+    for line in format_stats(stats, format) {
+        println!("{}", line);
+    }
+}
+
+/// Concatenates the files at `paths`, or reads standard input when there
+/// are none.
+fn read_inputs(paths: &[PathBuf]) -> Result<Vec<u8>> {
+    let mut input = Vec::new();
+    if paths.is_empty() {
+        let _ = stdin().read_to_end(&mut input)?;
+    }
+    for path in paths {
+        input.extend(fs::read(path)?);
+    }
+
+    Ok(input)
+}
+
+fn report_bundle(bundle: &Bundle) {
+    if bundle.prerequisites.is_empty() {
+        println!("The bundle records a complete history.");
+    } else {
+        println!(
+            "The bundle requires these {} commits:",
+            bundle.prerequisites.len()
+        );
+        for sha1 in &bundle.prerequisites {
+            println!("{}", sha1);
+        }
+    }
+    println!("The bundle contains these {} refs:", bundle.refs.len());
+    for (name, sha1) in &bundle.refs {
+        println!("{} {}", sha1, name);
+    }
+}
+
+fn report_conflicts(conflicts: &[PathBuf]) {
+    for path in conflicts {
+        println!("CONFLICT (content): Merge conflict in {}", path.display());
+    }
+}
+
+fn exec_path(exec: Option<PathBuf>) -> Result<PathBuf> {
+    match exec {
+        Some(path) => Ok(path),
+        None => Ok(env::current_exe()?),
+    }
+}
+
 mod opt {
     use std::path::PathBuf;
 
     use clap::Clap;
 
-    use baby_git_rs::Sha1Hash;
+    use baby_git_rs::{refs::RefSpec, Sha1Hash};
 
     #[derive(Debug, Clap)]
     #[clap(author, about, version)]
@@ -68,9 +819,23 @@ mod opt {
     pub enum SubCommand {
         InitDB,
         UpdateCache {
+            #[clap(short, long)]
+            recursive: bool,
+            #[clap(short, long)]
+            jobs: Option<usize>,
+            #[clap(long)]
+            progress: bool,
             #[clap(required = true)]
             paths: Vec<PathBuf>,
         },
+        Add {
+            #[clap(short, long)]
+            jobs: Option<usize>,
+            #[clap(long)]
+            progress: bool,
+            #[clap(default_value = ".")]
+            paths: Vec<PathBuf>,
+        },
         WriteTree,
         CommitTree {
             #[clap(name("tree hash"), parse(try_from_str))]
@@ -78,14 +843,372 @@ mod opt {
             #[clap(short, name("parent commit hash"), parse(try_from_str))]
             parents: Vec<Sha1Hash>,
         },
+        Commit {
+            #[clap(short)]
+            all: bool,
+            #[clap(short, conflicts_with("file"))]
+            message: Option<String>,
+            #[clap(short = 'F')]
+            file: Option<PathBuf>,
+            #[clap(long)]
+            allow_empty: bool,
+            #[clap(long)]
+            amend: bool,
+        },
+        Reset {
+            #[clap(long, group = "mode")]
+            soft: bool,
+            #[clap(long, group = "mode")]
+            mixed: bool,
+            #[clap(long, group = "mode")]
+            hard: bool,
+            #[clap(name("[commit] paths"))]
+            args: Vec<String>,
+        },
+        CherryPick {
+            #[clap(long = "continue", group = "action")]
+            continue_: bool,
+            #[clap(long, group = "action")]
+            abort: bool,
+            #[clap(long)]
+            allow_empty: bool,
+            #[clap(required_unless_present_any(&["continue", "abort"]))]
+            commit: Option<String>,
+        },
+        Revert {
+            #[clap(long = "continue", group = "action")]
+            continue_: bool,
+            #[clap(long, group = "action")]
+            abort: bool,
+            #[clap(long)]
+            allow_empty: bool,
+            #[clap(required_unless_present_any(&["continue", "abort"]))]
+            commit: Option<String>,
+        },
+        Rebase {
+            #[clap(long = "continue", group = "action")]
+            continue_: bool,
+            #[clap(long, group = "action")]
+            skip: bool,
+            #[clap(long, group = "action")]
+            abort: bool,
+            #[clap(long, name("todo file"))]
+            todo: Option<PathBuf>,
+            #[clap(required_unless_present_any(&["continue", "skip", "abort"]))]
+            upstream: Option<String>,
+        },
+        Blame {
+            #[clap(short, long)]
+            porcelain: bool,
+            path: PathBuf,
+            #[clap(name("revision"), default_value = "HEAD")]
+            rev: String,
+        },
+        DiffTree {
+            #[clap(short = 'M')]
+            renames: bool,
+            #[clap(short = 'C')]
+            copies: bool,
+            #[clap(long, default_value = "50")]
+            threshold: u32,
+            #[clap(short = 'l', default_value = "1000")]
+            limit: usize,
+            #[clap(long, group = "stat-format")]
+            stat: bool,
+            #[clap(long, group = "stat-format")]
+            numstat: bool,
+            #[clap(long, group = "stat-format")]
+            shortstat: bool,
+            old: String,
+            new: Option<String>,
+        },
+        Log {
+            #[clap(long, requires("path"))]
+            follow: bool,
+            #[clap(short = 'M')]
+            renames: bool,
+            #[clap(long)]
+            name_status: bool,
+            #[clap(long, group = "stat-format")]
+            stat: bool,
+            #[clap(long, group = "stat-format")]
+            numstat: bool,
+            #[clap(long, group = "stat-format")]
+            shortstat: bool,
+            #[clap(short = 'n', long)]
+            max_count: Option<usize>,
+            #[clap(name("revision"), default_value = "HEAD")]
+            rev: String,
+            #[clap(last = true)]
+            path: Option<PathBuf>,
+        },
+        FormatPatch {
+            #[clap(short, long, default_value = ".")]
+            output_directory: PathBuf,
+            #[clap(name("<since> or <revision range>"))]
+            range: String,
+        },
+        Apply {
+            #[clap(long)]
+            check: bool,
+            #[clap(long, group = "target")]
+            index: bool,
+            #[clap(long, group = "target")]
+            cached: bool,
+            #[clap(name("patch"))]
+            patches: Vec<PathBuf>,
+        },
+        Am {
+            #[clap(name("mbox"))]
+            mboxes: Vec<PathBuf>,
+        },
+        #[clap(alias = "checkout")]
+        Switch {
+            #[clap(short = 'c', short_alias = 'b', name("new branch"))]
+            create: Option<String>,
+            #[clap(long)]
+            detach: bool,
+            #[clap(name("branch or revision"), default_value = "HEAD")]
+            rev: String,
+        },
         ReadTree {
             #[clap(name("tree hash"), parse(try_from_str))]
             tree_hash: Sha1Hash,
         },
-        ShowDiff,
+        ShowDiff {
+            #[clap(long, group = "stat-format")]
+            stat: bool,
+            #[clap(long, group = "stat-format")]
+            numstat: bool,
+            #[clap(long, group = "stat-format")]
+            shortstat: bool,
+        },
         CatFile {
-            #[clap(name("object hash"), parse(try_from_str))]
-            object_hash: Sha1Hash,
+            #[clap(short = 't', group = "mode")]
+            show_type: bool,
+            #[clap(short = 's', group = "mode")]
+            show_size: bool,
+            #[clap(short, group = "mode")]
+            exists: bool,
+            #[clap(short, group = "mode")]
+            pretty: bool,
+            #[clap(long, group = "mode")]
+            batch: bool,
+            #[clap(long, group = "mode")]
+            batch_check: bool,
+            #[clap(
+                name("[type] object"),
+                max_values(2),
+                required_unless_present_any(&["batch", "batch-check"])
+            )]
+            args: Vec<String>,
+        },
+        HashObject {
+            #[clap(short)]
+            write: bool,
+            #[clap(long)]
+            stdin: bool,
+            #[clap(short = 't', default_value = "blob")]
+            obj_type: String,
+            #[clap(required_unless_present("stdin"))]
+            paths: Vec<PathBuf>,
+        },
+        LsFiles {
+            #[clap(short, long)]
+            cached: bool,
+            #[clap(short, long)]
+            deleted: bool,
+            #[clap(short, long)]
+            modified: bool,
+            #[clap(short, long)]
+            others: bool,
+            #[clap(short, long)]
+            stage: bool,
+            #[clap(short = 'z')]
+            zero_terminated: bool,
+        },
+        LsTree {
+            #[clap(short)]
+            recursive: bool,
+            #[clap(short = 't')]
+            show_trees: bool,
+            #[clap(long)]
+            name_only: bool,
+            #[clap(name("tree-ish"))]
+            tree_ish: String,
+            path: Option<String>,
+        },
+        CheckoutIndex {
+            #[clap(short, long)]
+            force: bool,
+            paths: Vec<PathBuf>,
+        },
+        UpdateRef {
+            #[clap(short, default_value = "update-ref")]
+            message: String,
+            #[clap(name("ref name"))]
+            name: String,
+            #[clap(name("revision"))]
+            rev: String,
+        },
+        UploadPack {
+            directory: PathBuf,
+        },
+        ReceivePack {
+            directory: PathBuf,
+        },
+        FetchPack {
+            #[clap(long, name("upload-pack program"))]
+            exec: Option<PathBuf>,
+            remote: PathBuf,
+            #[clap(name("ref name"))]
+            refs: Vec<String>,
+        },
+        Fetch {
+            #[clap(short, long)]
+            force: bool,
+            #[clap(long, name("upload-pack program"))]
+            exec: Option<PathBuf>,
+            #[clap(name("repository or bundle"))]
+            remote: PathBuf,
+            #[clap(name("refspec"), parse(try_from_str))]
+            specs: Vec<RefSpec>,
+        },
+        Clone {
+            #[clap(long, name("upload-pack program"))]
+            exec: Option<PathBuf>,
+            #[clap(name("repository or bundle"))]
+            remote: PathBuf,
+            directory: PathBuf,
+        },
+        Bundle {
+            #[clap(subcommand)]
+            cmd: BundleCommand,
+        },
+        SendPack {
+            #[clap(long, name("receive-pack program"))]
+            exec: Option<PathBuf>,
+            remote: PathBuf,
+            #[clap(name("refspec"), required = true, parse(try_from_str))]
+            refs: Vec<RefSpec>,
+        },
+        Push {
+            #[clap(short, long)]
+            force: bool,
+            #[clap(long, name("receive-pack program"))]
+            exec: Option<PathBuf>,
+            remote: PathBuf,
+            #[clap(name("refspec"), parse(try_from_str))]
+            specs: Vec<RefSpec>,
+        },
+        #[clap(alias = "gc")]
+        Prune {
+            #[clap(short = 'n', long)]
+            dry_run: bool,
+            #[clap(long, default_value = "14")]
+            grace_days: u64,
+        },
+        Reflog {
+            #[clap(subcommand)]
+            cmd: ReflogCommand,
+        },
+        Stash {
+            #[clap(subcommand)]
+            cmd: Option<StashCommand>,
+        },
+        Submodule {
+            #[clap(subcommand)]
+            cmd: SubmoduleCommand,
+        },
+        Bisect {
+            #[clap(subcommand)]
+            cmd: BisectCommand,
+        },
+    }
+
+    #[derive(Debug, Clap)]
+    pub enum BisectCommand {
+        Start {
+            bad: Option<String>,
+            good: Vec<String>,
+        },
+        Good {
+            #[clap(default_value = "HEAD")]
+            rev: String,
+        },
+        Bad {
+            #[clap(default_value = "HEAD")]
+            rev: String,
+        },
+        Skip {
+            #[clap(default_value = "HEAD")]
+            rev: String,
+        },
+        Reset,
+        Run {
+            #[clap(required = true)]
+            cmd: Vec<String>,
+        },
+    }
+
+    #[derive(Debug, Clap)]
+    pub enum BundleCommand {
+        Create {
+            #[clap(long)]
+            all: bool,
+            file: PathBuf,
+            #[clap(name("ref or range"), required_unless_present("all"))]
+            revs: Vec<String>,
+        },
+        Verify {
+            file: PathBuf,
+        },
+        Unbundle {
+            file: PathBuf,
+        },
+    }
+
+    #[derive(Debug, Clap)]
+    pub enum StashCommand {
+        Push {
+            #[clap(short)]
+            message: Option<String>,
+        },
+        List,
+        Show {
+            stash: Option<String>,
+        },
+        Apply {
+            stash: Option<String>,
+        },
+        Pop {
+            stash: Option<String>,
+        },
+        Drop {
+            stash: Option<String>,
+        },
+    }
+
+    #[derive(Debug, Clap)]
+    pub enum SubmoduleCommand {
+        Init,
+        Update {
+            #[clap(long, name("upload-pack program"))]
+            exec: Option<PathBuf>,
+        },
+    }
+
+    #[derive(Debug, Clap)]
+    pub enum ReflogCommand {
+        Show {
+            #[clap(name("ref name"))]
+            name: Option<String>,
+        },
+        Expire {
+            #[clap(long, default_value = "90")]
+            expire_days: i64,
+            #[clap(name("ref name"))]
+            names: Vec<String>,
         },
     }
 }
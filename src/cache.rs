@@ -1,6 +1,6 @@
 use std::{
     collections::BTreeMap,
-    fs::{File, Metadata},
+    fs::{self, File, Metadata},
     os::unix::prelude::MetadataExt,
     path::{Path, PathBuf},
 };
@@ -9,7 +9,7 @@ use bincode::{deserialize_from, serialize_into};
 use clap::crate_version;
 use serde::{Deserialize, Serialize};
 
-use crate::{Result, Sha1Hash};
+use crate::{Result, Sha1Hash, EXECUTABLE_MODE, GITLINK_MODE, REGULAR_FILE_MODE, SYMLINK_MODE};
 
 const CACHE_SIGNATURE: u32 = 0x44495243;
 const MTIME_CHANGED: u64 = 0x0001;
@@ -39,11 +39,80 @@ impl Cache {
     }
 
     pub fn insert(&mut self, src_path: PathBuf, sha1: Sha1Hash) -> Result<()> {
-        let entry = CacheEntry::new(src_path.clone(), sha1)?;
-        let _ = self.entries.insert(src_path, entry);
+        self.insert_in(Path::new(""), src_path, sha1)
+    }
+
+    /// Adds an entry named `name`, taking its stat data from the file at
+    /// `name` below `work_dir`.
+    pub fn insert_in(&mut self, work_dir: &Path, name: PathBuf, sha1: Sha1Hash) -> Result<()> {
+        let entry = CacheEntry::new(&work_dir.join(&name), name.clone(), sha1)?;
+        let _ = self.entries.insert(name, entry);
 
         Ok(())
     }
+
+    /// Adds an entry for an object taken from a tree rather than the work
+    /// tree. It has no stat data, so the file reads as changed until it is
+    /// staged again.
+    pub fn insert_unstated(&mut self, name: PathBuf, st_mode: u32, sha1: Sha1Hash) {
+        let entry = CacheEntry {
+            ctime: 0,
+            ctime_nsec: 0,
+            mtime: 0,
+            mtime_nsec: 0,
+            st_dev: 0,
+            st_ino: 0,
+            st_mode,
+            st_uid: 0,
+            st_gid: 0,
+            st_size: 0,
+            sha1,
+            name: name.clone(),
+        };
+        let _ = self.entries.insert(name, entry);
+    }
+}
+
+/// An exclusive `index.lock` on the cache. Committing renames the lock over
+/// the index; dropping an uncommitted lock releases it untouched.
+#[derive(Debug)]
+pub struct CacheLock {
+    cache_path: PathBuf,
+    lock_path: PathBuf,
+    lock_file: Option<File>,
+}
+
+impl CacheLock {
+    pub fn acquire(cache_path: &Path) -> Result<Self> {
+        let lock_path = cache_path.with_file_name("index.lock");
+        let lock_file = fs::OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&lock_path)?;
+
+        Ok(Self {
+            cache_path: cache_path.to_owned(),
+            lock_path,
+            lock_file: Some(lock_file),
+        })
+    }
+
+    pub fn commit(mut self, cache: &Cache) -> Result<()> {
+        if let Some(lock_file) = self.lock_file.take() {
+            cache.write_cache(&lock_file)?;
+            fs::rename(&self.lock_path, &self.cache_path)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        if self.lock_file.take().is_some() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -78,8 +147,8 @@ pub struct CacheEntry {
 }
 
 impl CacheEntry {
-    fn new(path: PathBuf, sha1: Sha1Hash) -> Result<Self> {
-        let meta = path.metadata()?;
+    fn new(path: &Path, name: PathBuf, sha1: Sha1Hash) -> Result<Self> {
+        let meta = path.symlink_metadata()?;
         let entry = Self {
             ctime: meta.ctime(),
             ctime_nsec: meta.ctime_nsec(),
@@ -87,12 +156,12 @@ impl CacheEntry {
             mtime_nsec: meta.mtime_nsec(),
             st_dev: meta.dev(),
             st_ino: meta.ino(),
-            st_mode: meta.mode(),
+            st_mode: cache_mode(&meta),
             st_uid: meta.uid(),
             st_gid: meta.gid(),
             st_size: meta.size(),
             sha1,
-            name: path,
+            name,
         };
         Ok(entry)
     }
@@ -109,7 +178,7 @@ impl CacheEntry {
         if self.st_uid != meta.uid() || self.st_gid != meta.gid() {
             changed |= OWNER_CHANGED;
         }
-        if self.st_mode != meta.mode() {
+        if self.st_mode != cache_mode(&meta) {
             changed |= MODE_CHANGED;
         }
         if self.st_dev != meta.dev() || self.st_ino != meta.ino() {
@@ -122,3 +191,18 @@ impl CacheEntry {
         changed
     }
 }
+
+/// Reduces a file's mode to what the cache tracks: symlinks, regular files
+/// with or without the executable bit, and directories holding a nested
+/// repository.
+pub fn cache_mode(meta: &Metadata) -> u32 {
+    if meta.file_type().is_symlink() {
+        SYMLINK_MODE
+    } else if meta.is_dir() {
+        GITLINK_MODE
+    } else if meta.mode() & 0o111 != 0 {
+        EXECUTABLE_MODE
+    } else {
+        REGULAR_FILE_MODE
+    }
+}
@@ -1,12 +1,44 @@
+mod am;
+mod apply;
+mod bisect;
+mod blame;
+mod bundle;
 mod cat_file;
+mod checkout_index;
+mod cherry_pick;
+mod clone;
+mod commit;
 mod commit_tree;
+mod diff_tree;
+mod fetch;
+mod fetch_pack;
+mod format_patch;
+mod hash_object;
 mod init_db;
+mod log;
+mod ls_files;
+mod ls_tree;
+mod prune;
+mod push;
 mod read_tree;
+mod rebase;
+mod receive_pack;
+mod reflog;
+mod reset;
+mod send_pack;
 mod show_diff;
+mod stash;
+mod submodule;
+mod switch;
 mod update_cache;
+mod update_ref;
+mod upload_pack;
 mod write_tree;
 
-use std::{fs::File, path::PathBuf};
+use std::{
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+};
 
 use rand::{
     distributions::Alphanumeric,
@@ -16,26 +48,59 @@ use tracing::debug;
 
 use crate::Result;
 
-pub use cat_file::cat_file;
+pub use am::{am, split_mbox, MailPatch};
+pub use apply::{apply, ApplyTarget};
+pub use bisect::{bisect_check, bisect_mark, bisect_reset, bisect_start, BisectStep, BisectTerm};
+pub use blame::{blame, format_blame, BlameLine};
+pub use bundle::{bundle_create, bundle_unbundle, bundle_verify, is_bundle, Bundle};
+pub use cat_file::{cat_file, cat_file_batch, cat_file_info, cat_file_to};
+pub use checkout_index::checkout_index;
+pub use cherry_pick::{cherry_pick, cherry_pick_abort, cherry_pick_continue, revert, PickOutcome};
+pub use clone::clone;
+pub use commit::commit;
 pub use commit_tree::commit_tree;
+pub use diff_tree::diff_tree;
+pub use fetch::fetch;
+pub use fetch_pack::fetch_pack;
+pub use format_patch::format_patch;
+pub use hash_object::hash_object;
 pub use init_db::init_db;
+pub use log::{log, LogEntry, LogOptions};
+pub use ls_files::{ls_files, LsFilesFilter};
+pub use ls_tree::ls_tree;
+pub use prune::{prune, PruneReport};
+pub use push::push;
 pub use read_tree::read_tree;
-pub use show_diff::show_diff;
-pub use update_cache::update_cache;
+pub use rebase::{rebase, rebase_abort, rebase_continue, rebase_skip, RebaseOutcome};
+pub use receive_pack::receive_pack;
+pub use reflog::{reflog_expire, reflog_show};
+pub use reset::{reset, reset_paths, ResetMode};
+pub use send_pack::send_pack;
+pub use show_diff::{show_diff, show_diff_stats};
+pub use stash::{stash_apply, stash_drop, stash_list, stash_push, stash_show};
+pub use submodule::{submodule_init, submodule_update};
+pub use switch::switch;
+pub use update_cache::{expand_paths, update_cache, update_tracked};
+pub use update_ref::update_ref;
+pub use upload_pack::upload_pack;
 pub use write_tree::write_tree;
 
 const TEMPLATE: &str = "temp_git_file_";
 
-fn tempfile() -> Result<(PathBuf, File)> {
+/// Creates a new file with a random name in `dir`, returning its path.
+pub(crate) fn tempfile(dir: &Path) -> Result<(PathBuf, File)> {
     let mut rng = thread_rng();
     let suffix = (0..6)
         .map(|_| rng.sample(Alphanumeric))
         .map(char::from)
         .collect::<String>();
 
-    let filename = PathBuf::from(format!("{}{}", TEMPLATE, suffix));
+    let filename = dir.join(format!("{}{}", TEMPLATE, suffix));
     debug!("temp file {:?}", &filename);
 
-    let file = File::create(&filename)?;
+    let file = OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(&filename)?;
     Ok((filename, file))
 }
@@ -0,0 +1,181 @@
+use chrono::{DateTime, Utc};
+use tracing::debug;
+
+use super::{apply, cherry_pick::commit_on_head, rebase::check_clean, write_tree, ApplyTarget};
+use crate::{
+    diff::{parse_patch, FilePatch},
+    refs, Config, GitError, GitObject, Result, Sha1Hash,
+};
+
+/// A commit read from a mail: its author line, message and patch.
+#[derive(Debug)]
+pub struct MailPatch {
+    pub author_info: String,
+    pub message: String,
+    pub patches: Vec<FilePatch>,
+}
+
+impl MailPatch {
+    pub fn subject(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+}
+
+/// Splits an mbox into its mails. The author comes from the `From` and
+/// `Date` headers, defaulting to the configured author and the current
+/// date; the message from the `Subject`, without its `[PATCH]` prefix, and
+/// the body up to the `---` line before the patch.
+pub fn split_mbox(mbox: &[u8], config: &Config) -> Result<Vec<MailPatch>> {
+    let lines = mbox.split_inclusive(|&b| b == b'\n').collect::<Vec<_>>();
+
+    let mut starts = (0..lines.len())
+        .filter(|&i| {
+            lines[i].starts_with(b"From ")
+                && (i == 0 || lines[i - 1].iter().all(u8::is_ascii_whitespace))
+                && lines.get(i + 1).is_some_and(|next| is_header(next))
+        })
+        .collect::<Vec<_>>();
+    if starts.first() != Some(&0) {
+        starts.insert(0, 0);
+    }
+    starts.push(lines.len());
+
+    starts
+        .windows(2)
+        .map(|range| parse_mail(&lines[range[0]..range[1]], config))
+        .collect()
+}
+
+/// Commits the patch of `mail` on top of `HEAD` with the mail's author and
+/// message. The cache and work tree must match `HEAD`; when the patch does
+/// not apply, nothing is changed.
+pub fn am(mail: &MailPatch, config: &Config) -> Result<Sha1Hash> {
+    if mail.patches.is_empty() {
+        return Err(GitError::ParsePatchError(format!(
+            "no patch in {:?}",
+            mail.subject()
+        )));
+    }
+    if let Some(head) = refs::read_ref(refs::HEAD, config)? {
+        let head_commit = GitObject::read_commit(&config.obj_db_path, &head)?;
+        check_clean(head_commit.tree(), config)?;
+    }
+
+    let _ = apply(&mail.patches, ApplyTarget::Index, false, config)?;
+    let tree_hash = write_tree(config)?;
+    debug!("applied {:?} as tree {}", mail.subject(), tree_hash);
+
+    commit_on_head(
+        tree_hash,
+        false,
+        mail.author_info.clone(),
+        mail.message.clone(),
+        "am",
+        config,
+    )
+}
+
+/// Parses a mail. Its headers and message are text, while the patch after
+/// them is kept as it is, whatever the encoding of the files it changes.
+fn parse_mail(lines: &[&[u8]], config: &Config) -> Result<MailPatch> {
+    let mut lines = lines.iter().copied().peekable();
+    if lines.peek().is_some_and(|line| line.starts_with(b"From ")) {
+        let _ = lines.next();
+    }
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in lines.by_ref() {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches('\n');
+        if line.trim().is_empty() {
+            break;
+        }
+        match headers.last_mut() {
+            Some((_, value)) if line.starts_with(' ') || line.starts_with('\t') => {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            _ => match line.split_once(':') {
+                Some((name, value)) => {
+                    headers.push((name.to_ascii_lowercase(), value.trim().to_owned()))
+                }
+                None => return Err(GitError::ParsePatchError(format!("mail header {:?}", line))),
+            },
+        }
+    }
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let mut body = Vec::new();
+    let mut patch = Vec::new();
+    for line in lines.by_ref() {
+        let text = String::from_utf8_lossy(line);
+        let text = text.trim_end_matches('\n');
+        if text == "---" || text.starts_with("diff --git ") || text.starts_with("--- ") {
+            patch.extend_from_slice(line);
+            break;
+        }
+        body.push(text.to_owned());
+    }
+    for line in lines {
+        patch.extend_from_slice(line);
+    }
+
+    let subject = strip_prefixes(header("subject").unwrap_or_default());
+    let body = body.join("\n");
+    let message = if body.trim().is_empty() {
+        format!("{}\n", subject)
+    } else {
+        format!("{}\n\n{}\n", subject, body.trim())
+    };
+
+    let date = header("date")
+        .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+        .map(|date| date.with_timezone(&Utc))
+        .unwrap_or(config.commit_date);
+    let author_info = match header("from") {
+        Some(from) => {
+            let (name, email) = match from.split_once('<') {
+                Some((name, email)) => (name.trim().trim_matches('"'), email.trim_end_matches('>')),
+                None => ("", from),
+            };
+            format!("{},,, <{}> {}", name, email, date)
+        }
+        None => format!(
+            "{},,, <{}> {}",
+            config.author_name, config.author_email, date
+        ),
+    };
+
+    Ok(MailPatch {
+        author_info,
+        message,
+        patches: parse_patch(&patch)?,
+    })
+}
+
+/// Whether `line` looks like a mail header, `Name: value`.
+fn is_header(line: &[u8]) -> bool {
+    line.iter().position(|&b| b == b':').is_some_and(|colon| {
+        colon > 0
+            && line[..colon]
+                .iter()
+                .all(|&b| b.is_ascii_alphanumeric() || b == b'-')
+    })
+}
+
+/// Drops the bracketed prefixes, like `[PATCH 1/2]`, of a subject.
+fn strip_prefixes(mut subject: &str) -> &str {
+    while let Some(rest) = subject.strip_prefix('[') {
+        subject = match rest.split_once(']') {
+            Some((_, rest)) => rest.trim_start(),
+            None => break,
+        };
+    }
+
+    subject
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    os::unix::ffi::OsStringExt,
+    path::{Path, PathBuf},
+};
+
+use tracing::debug;
+
+use crate::{
+    cache::cache_mode,
+    diff::{apply_hunks, FilePatch},
+    merge::read_blob,
+    worktree, Blob, Cache, CacheLock, Config, GitError, GitObject, Result, Sha1Hash, GITLINK_MODE,
+    REGULAR_FILE_MODE, SYMLINK_MODE,
+};
+
+/// What `apply` changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApplyTarget {
+    /// The files in the work tree.
+    WorkTree,
+    /// The work tree files and their cache entries, which must agree.
+    Index,
+    /// Only the cache entries.
+    Cached,
+}
+
+/// Applies `patches` to `target`, all of them or, when one does not apply,
+/// none. With `check`, nothing is written. Returns the paths changed.
+pub fn apply(
+    patches: &[FilePatch],
+    target: ApplyTarget,
+    check: bool,
+    config: &Config,
+) -> Result<Vec<PathBuf>> {
+    let lock = if check || target == ApplyTarget::WorkTree {
+        None
+    } else {
+        Some(CacheLock::acquire(&config.cache_path)?)
+    };
+    let mut cache = Cache::read_cache(&config.cache_path)?;
+
+    // The new content and mode of each path, or `None` when it is deleted.
+    let mut results: BTreeMap<PathBuf, Option<(Vec<u8>, u32)>> = BTreeMap::new();
+    for patch in patches {
+        let path = patch.path().to_owned();
+        if patch.binary {
+            return Err(GitError::BinaryPatch(path));
+        }
+
+        let preimage = match &patch.old_path {
+            Some(old_path) => match results.get(old_path) {
+                Some(result) => result.clone(),
+                None => read_preimage(old_path, target, &cache, config)?,
+            },
+            None => None,
+        };
+        let (old_content, old_mode) = match (preimage, &patch.old_path) {
+            (Some(preimage), Some(_)) => preimage,
+            (None, Some(old_path)) => return Err(GitError::PatchDoesNotApply(old_path.clone())),
+            (_, None) => {
+                let exists = match results.get(&path) {
+                    Some(result) => result.is_some(),
+                    None => read_preimage(&path, target, &cache, config)?.is_some(),
+                };
+                if exists {
+                    return Err(GitError::PatchDoesNotApply(path));
+                }
+                (Vec::new(), REGULAR_FILE_MODE)
+            }
+        };
+
+        let content = apply_hunks(&old_content, &patch.hunks)
+            .ok_or_else(|| GitError::PatchDoesNotApply(path.clone()))?;
+        if let Some(old_path) = &patch.old_path {
+            if patch.new_path.as_ref() != Some(old_path) {
+                let _ = results.insert(old_path.clone(), None);
+            }
+        }
+        match &patch.new_path {
+            Some(new_path) => {
+                let mode = patch.new_mode.unwrap_or(old_mode);
+                let _ = results.insert(new_path.clone(), Some((content, mode)));
+            }
+            // A deletion must take all of the file with it.
+            None if !content.is_empty() => return Err(GitError::PatchDoesNotApply(path)),
+            None => {}
+        }
+    }
+    for (path, result) in &results {
+        if result.is_some() {
+            worktree::check_symlink_ancestors(path, |parent| match results.get(parent) {
+                Some(result) => result.as_ref().map(|(_, mode)| *mode),
+                None => cache.entries.get(parent).map(|entry| entry.st_mode),
+            })?;
+        }
+    }
+    let paths = results.keys().cloned().collect::<Vec<_>>();
+    if check {
+        return Ok(paths);
+    }
+
+    for (path, result) in results {
+        let work_path = config.work_dir.join(&path);
+        match result {
+            None => {
+                if target != ApplyTarget::Cached {
+                    worktree::remove_file(&work_path)?;
+                }
+                let _ = cache.entries.remove(&path);
+            }
+            Some((content, GITLINK_MODE)) => {
+                let sha1 = gitlink_commit(&content, &path)?;
+                cache.insert_unstated(path, GITLINK_MODE, sha1);
+            }
+            Some((content, mode)) => {
+                let sha1 = GitObject::Blob(Blob::new(content)).write_sha1_file(config)?;
+                debug!("{:?} -> {}", path, sha1);
+                match target {
+                    ApplyTarget::WorkTree => {
+                        worktree::checkout_file(&work_path, mode, &sha1, config)?
+                    }
+                    ApplyTarget::Index => {
+                        worktree::checkout_file(&work_path, mode, &sha1, config)?;
+                        cache.insert_in(&config.work_dir, path, sha1)?;
+                    }
+                    ApplyTarget::Cached => cache.insert_unstated(path, mode, sha1),
+                }
+            }
+        }
+    }
+    if let Some(lock) = lock {
+        lock.commit(&cache)?;
+    }
+
+    Ok(paths)
+}
+
+/// Reads the content and mode `path` has in `target`, or `None` if it does
+/// not exist there. With `Index`, the work tree file must match its cache
+/// entry.
+fn read_preimage(
+    path: &Path,
+    target: ApplyTarget,
+    cache: &Cache,
+    config: &Config,
+) -> Result<Option<(Vec<u8>, u32)>> {
+    let entry = cache.entries.get(path);
+    if let Some(entry) = entry.filter(|entry| entry.st_mode == GITLINK_MODE) {
+        return Ok(Some((
+            format!("Subproject commit {}\n", entry.sha1).into_bytes(),
+            GITLINK_MODE,
+        )));
+    }
+    if target == ApplyTarget::Cached {
+        return entry
+            .map(|entry| Ok((read_blob(&entry.sha1, config)?, entry.st_mode)))
+            .transpose();
+    }
+
+    let work_path = config.work_dir.join(path);
+    let meta = match work_path.symlink_metadata() {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return match (target, entry) {
+                (ApplyTarget::Index, Some(_)) => Err(GitError::LocalChanges(vec![path.to_owned()])),
+                _ => Ok(None),
+            };
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mode = cache_mode(&meta);
+    let content = if mode == SYMLINK_MODE {
+        fs::read_link(&work_path)?.into_os_string().into_vec()
+    } else {
+        fs::read(&work_path)?
+    };
+
+    if target == ApplyTarget::Index {
+        let matches = match entry {
+            Some(entry) => {
+                entry.match_stat(meta) == 0
+                    || (entry.st_mode == mode && read_blob(&entry.sha1, config)? == content)
+            }
+            None => false,
+        };
+        if !matches {
+            return Err(GitError::LocalChanges(vec![path.to_owned()]));
+        }
+    }
+
+    Ok(Some((content, mode)))
+}
+
+/// The commit a patched gitlink points at, from its `Subproject commit`
+/// line.
+fn gitlink_commit(content: &[u8], path: &Path) -> Result<Sha1Hash> {
+    String::from_utf8_lossy(content)
+        .trim_end()
+        .strip_prefix("Subproject commit ")
+        .and_then(|sha1| sha1.parse().ok())
+        .ok_or_else(|| GitError::PatchDoesNotApply(path.to_owned()))
+}
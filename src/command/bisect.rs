@@ -0,0 +1,255 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    io::ErrorKind,
+    process::Command,
+    slice,
+};
+
+use tracing::debug;
+
+use super::switch;
+use crate::{refs, Config, GitError, GitObject, Result, Sha1Hash};
+
+const BISECT_START: &str = "BISECT_START";
+const BISECT_PREFIX: &str = "refs/bisect/";
+const BAD_REF: &str = "refs/bisect/bad";
+
+/// How a commit behaved in a bisection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BisectTerm {
+    Good,
+    Bad,
+    Skip,
+}
+
+/// What the bisection needs next.
+#[derive(Debug)]
+pub enum BisectStep {
+    /// A good and a bad commit are still needed to narrow the range.
+    Waiting,
+    /// `commit` is checked out for testing. `remaining` commits are left to
+    /// test after it, at worst.
+    Candidate {
+        commit: Sha1Hash,
+        subject: String,
+        remaining: usize,
+    },
+    /// The first bad commit.
+    Found(Sha1Hash),
+    /// Only skipped commits are left; any of these may be the first bad one.
+    OnlySkipped(Vec<Sha1Hash>),
+}
+
+/// Starts a bisection, remembering what `HEAD` points at for
+/// `bisect_reset`. Any earlier bisection is forgotten. `bad` and `good`
+/// commits may be given at once.
+pub fn bisect_start(
+    bad: Option<&Sha1Hash>,
+    good: &[Sha1Hash],
+    config: &Config,
+) -> Result<BisectStep> {
+    let start = match refs::read_symref(refs::HEAD, config)? {
+        Some(target) => target
+            .strip_prefix(refs::HEADS_PREFIX)
+            .unwrap_or(&target)
+            .to_owned(),
+        None => refs::read_ref(refs::HEAD, config)?
+            .ok_or_else(|| GitError::UnknownRevision(refs::HEAD.to_owned()))?
+            .to_string(),
+    };
+    if !config.repo_path.join(BISECT_START).exists() {
+        fs::write(config.repo_path.join(BISECT_START), format!("{}\n", start))?;
+    }
+    clear_terms(config)?;
+
+    if let Some(bad) = bad {
+        mark(BisectTerm::Bad, bad, config)?;
+    }
+    for commit_hash in good {
+        mark(BisectTerm::Good, commit_hash, config)?;
+    }
+
+    next_step(config)
+}
+
+/// Marks `commit_hash` as good, bad or skipped and checks out the next
+/// commit to test.
+pub fn bisect_mark(
+    term: BisectTerm,
+    commit_hash: &Sha1Hash,
+    config: &Config,
+) -> Result<BisectStep> {
+    check_bisecting(config)?;
+    mark(term, commit_hash, config)?;
+
+    next_step(config)
+}
+
+/// Runs the program and arguments `cmd` to test the checked out commit.
+/// Exit code 0 means good, 125 skip, and other codes below 128 bad; higher
+/// codes and signals abort the bisection.
+pub fn bisect_check(cmd: &[String], config: &Config) -> Result<BisectTerm> {
+    check_bisecting(config)?;
+
+    let failed = || GitError::BisectRunFailed(cmd.join(" "));
+    let (program, args) = cmd.split_first().ok_or_else(failed)?;
+    let status = Command::new(program).args(args).status()?;
+    debug!("{:?} exited with {:?}", cmd, status.code());
+    match status.code() {
+        Some(0) => Ok(BisectTerm::Good),
+        Some(125) => Ok(BisectTerm::Skip),
+        Some(code) if code < 128 => Ok(BisectTerm::Bad),
+        _ => Err(failed()),
+    }
+}
+
+/// Ends the bisection, checking out what `HEAD` pointed at when it started.
+pub fn bisect_reset(config: &Config) -> Result<()> {
+    let start = match fs::read_to_string(config.repo_path.join(BISECT_START)) {
+        Ok(start) => start.trim_end().to_owned(),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(GitError::NoOperation("bisect".to_owned()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    switch(&start, None, false, config)?;
+    clear_terms(config)?;
+    fs::remove_file(config.repo_path.join(BISECT_START))?;
+
+    Ok(())
+}
+
+fn mark(term: BisectTerm, commit_hash: &Sha1Hash, config: &Config) -> Result<()> {
+    let _ = GitObject::read_commit(&config.obj_db_path, commit_hash)?;
+    let name = match term {
+        BisectTerm::Bad => BAD_REF.to_owned(),
+        BisectTerm::Good => format!("{}good-{}", BISECT_PREFIX, commit_hash),
+        BisectTerm::Skip => format!("{}skip-{}", BISECT_PREFIX, commit_hash),
+    };
+
+    refs::set_ref(&name, commit_hash, config)
+}
+
+/// Picks the candidate that splits the commits reachable from the bad commit
+/// but not from any good one most evenly, and checks it out.
+fn next_step(config: &Config) -> Result<BisectStep> {
+    let mut bad = None;
+    let mut good = Vec::new();
+    let mut skipped = HashSet::new();
+    for (name, sha1) in refs::list_refs(config)? {
+        match name.strip_prefix(BISECT_PREFIX) {
+            Some("bad") => bad = Some(sha1),
+            Some(rest) if rest.starts_with("good-") => good.push(sha1),
+            Some(rest) if rest.starts_with("skip-") => {
+                let _ = skipped.insert(sha1);
+            }
+            _ => {}
+        }
+    }
+    let bad = match bad {
+        Some(bad) if !good.is_empty() => bad,
+        _ => return Ok(BisectStep::Waiting),
+    };
+
+    let good_commits = ancestors(&good, &HashMap::new(), config)?;
+    let candidates = ancestors(slice::from_ref(&bad), &good_commits, config)?;
+    if candidates.is_empty() {
+        return Err(GitError::BisectBadIsGood(bad));
+    }
+    let total = candidates.len();
+    debug!("{} candidates", total);
+
+    let mut names = candidates.keys().collect::<Vec<_>>();
+    names.sort();
+    let mut best = None;
+    for sha1 in names {
+        if sha1 == &bad || skipped.contains(sha1) {
+            continue;
+        }
+        let below = count_below(sha1, &candidates);
+        let score = below.min(total - below);
+        match best {
+            Some((_, best_score, _)) if best_score >= score => {}
+            _ => best = Some((sha1.clone(), score, below)),
+        }
+    }
+
+    match best {
+        Some((commit, _, below)) => {
+            switch(&commit.to_string(), None, true, config)?;
+            let commit_obj = GitObject::read_commit(&config.obj_db_path, &commit)?;
+            Ok(BisectStep::Candidate {
+                subject: commit_obj
+                    .user_comment()
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_owned(),
+                commit,
+                remaining: (below - 1).max(total - below - 1),
+            })
+        }
+        None if total == 1 => Ok(BisectStep::Found(bad)),
+        None => {
+            let mut left = candidates.into_keys().collect::<Vec<_>>();
+            left.sort();
+            Ok(BisectStep::OnlySkipped(left))
+        }
+    }
+}
+
+/// Maps the commits reachable from `tips` that are not in `exclude` to
+/// their parents.
+fn ancestors(
+    tips: &[Sha1Hash],
+    exclude: &HashMap<Sha1Hash, Vec<Sha1Hash>>,
+    config: &Config,
+) -> Result<HashMap<Sha1Hash, Vec<Sha1Hash>>> {
+    let mut commits = HashMap::new();
+    let mut queue = tips.iter().cloned().collect::<VecDeque<_>>();
+    while let Some(sha1) = queue.pop_front() {
+        if exclude.contains_key(&sha1) || commits.contains_key(&sha1) {
+            continue;
+        }
+        let commit_obj = GitObject::read_commit(&config.obj_db_path, &sha1)?;
+        queue.extend(commit_obj.parents().iter().cloned());
+        let _ = commits.insert(sha1, commit_obj.parents().to_vec());
+    }
+
+    Ok(commits)
+}
+
+/// Counts `start` and the commits of `commits` reachable from it.
+fn count_below(start: &Sha1Hash, commits: &HashMap<Sha1Hash, Vec<Sha1Hash>>) -> usize {
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from(vec![start]);
+    while let Some(sha1) = queue.pop_front() {
+        if let Some(parents) = commits.get(sha1) {
+            if seen.insert(sha1) {
+                queue.extend(parents);
+            }
+        }
+    }
+
+    seen.len()
+}
+
+fn check_bisecting(config: &Config) -> Result<()> {
+    if config.repo_path.join(BISECT_START).exists() {
+        Ok(())
+    } else {
+        Err(GitError::NoOperation("bisect".to_owned()))
+    }
+}
+
+fn clear_terms(config: &Config) -> Result<()> {
+    for (name, _) in refs::list_refs(config)? {
+        if name.starts_with(BISECT_PREFIX) {
+            refs::delete_ref(&name, config)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::Path,
+};
+
+use tracing::debug;
+
+use crate::{
+    diff::{matching_lines, split_lines},
+    ident_timestamp,
+    merge::read_blob,
+    split_ident, Config, GitError, GitObject, Result, Sha1Hash,
+};
+
+/// A line of the blamed file and the commit that last touched it.
+#[derive(Debug)]
+pub struct BlameLine {
+    pub commit: Sha1Hash,
+    /// The line number in `commit`'s version of the file, from 1.
+    pub orig_line: usize,
+    pub content: Vec<u8>,
+}
+
+/// Attributes each line of `path` as of `commit_hash` to the commit that
+/// last changed it. Lines are followed back through the parents by diffing
+/// each version of the file against the parents' versions; a line is blamed
+/// on the first commit none of whose parents has it.
+pub fn blame(path: &Path, commit_hash: &Sha1Hash, config: &Config) -> Result<Vec<BlameLine>> {
+    let content = match file_version(path, commit_hash, config)? {
+        Some(sha1) => read_blob(&sha1, config)?,
+        None => {
+            return Err(GitError::PathNotInTree(
+                path.to_owned(),
+                commit_hash.clone(),
+            ))
+        }
+    };
+    let lines = split_lines(&content);
+
+    let mut blamed = vec![None; lines.len()];
+    let mut pending = HashMap::new();
+    let _ = pending.insert(
+        commit_hash.clone(),
+        (0..lines.len()).map(|i| (i, i)).collect::<Vec<_>>(),
+    );
+    let mut queue = VecDeque::from(vec![commit_hash.clone()]);
+    let mut versions = HashMap::new();
+
+    while let Some(sha1) = queue.pop_front() {
+        let mut todo = match pending.remove(&sha1) {
+            Some(todo) => todo,
+            None => continue,
+        };
+        let (blob, content) = cached_version(&mut versions, path, &sha1, config)?
+            .cloned()
+            .expect("pending lines exist in this version");
+        let version_lines = split_lines(&content);
+        let commit_obj = GitObject::read_commit(&config.obj_db_path, &sha1)?;
+
+        for parent in commit_obj.parents() {
+            if todo.is_empty() {
+                break;
+            }
+            let parent_version = match cached_version(&mut versions, path, parent, config)? {
+                Some(parent_version) => parent_version,
+                None => continue,
+            };
+
+            let passed = if parent_version.0 == blob {
+                todo.split_off(0)
+            } else {
+                let parent_lines = split_lines(&parent_version.1);
+                let mut parent_of = vec![None; version_lines.len()];
+                for (i, j) in matching_lines(&parent_lines, &version_lines) {
+                    parent_of[j] = Some(i);
+                }
+
+                let mut passed = Vec::new();
+                todo.retain(|&(final_index, index)| match parent_of[index] {
+                    Some(parent_index) => {
+                        passed.push((final_index, parent_index));
+                        false
+                    }
+                    None => true,
+                });
+                passed
+            };
+
+            if !passed.is_empty() {
+                if !pending.contains_key(parent) {
+                    queue.push_back(parent.clone());
+                }
+                pending.entry(parent.clone()).or_default().extend(passed);
+            }
+        }
+
+        debug!("{} lines blamed on {}", todo.len(), sha1);
+        for (final_index, index) in todo {
+            blamed[final_index] = Some((sha1.clone(), index + 1));
+        }
+    }
+
+    Ok(blamed
+        .into_iter()
+        .zip(lines)
+        .map(|(blamed, line)| {
+            let (commit, orig_line) = blamed.expect("every line is blamed");
+            BlameLine {
+                commit,
+                orig_line,
+                content: line.to_vec(),
+            }
+        })
+        .collect())
+}
+
+/// Formats blamed lines like `git blame`: the short commit hash, author,
+/// date and line number before each line, or with `porcelain`, a header of
+/// `<commit> <orig line> <final line>` followed by the commit's details the
+/// first time it appears, and the line itself after a tab.
+pub fn format_blame(
+    lines: &[BlameLine],
+    path: &Path,
+    porcelain: bool,
+    config: &Config,
+) -> Result<Vec<String>> {
+    let mut commits = HashMap::new();
+    for line in lines {
+        if !commits.contains_key(&line.commit) {
+            let commit_obj = GitObject::read_commit(&config.obj_db_path, &line.commit)?;
+            let _ = commits.insert(line.commit.clone(), commit_obj);
+        }
+    }
+
+    let width = lines.len().to_string().len();
+    let mut seen = HashSet::new();
+    let mut output = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let commit_obj = &commits[&line.commit];
+        let content = String::from_utf8_lossy(&line.content);
+        let content = content.trim_end_matches('\n');
+        if !porcelain {
+            let (name, _, date) = split_ident(commit_obj.author_info());
+            output.push(format!(
+                "{} ({} {} {:>width$}) {}",
+                &line.commit.to_string()[..8],
+                name,
+                date.get(..19).unwrap_or(date),
+                i + 1,
+                content,
+                width = width
+            ));
+            continue;
+        }
+
+        output.push(format!("{} {} {}", line.commit, line.orig_line, i + 1));
+        if seen.insert(&line.commit) {
+            for (role, info) in [
+                ("author", commit_obj.author_info()),
+                ("committer", commit_obj.committer_info()),
+            ] {
+                let (name, email, _) = split_ident(info);
+                output.push(format!("{} {}", role, name));
+                output.push(format!("{}-mail <{}>", role, email));
+                output.push(format!("{}-time {}", role, ident_timestamp(info)));
+                output.push(format!("{}-tz +0000", role));
+            }
+            let subject = commit_obj.user_comment().lines().next().unwrap_or_default();
+            output.push(format!("summary {}", subject));
+            if let Some(parent) = commit_obj.parents().first() {
+                output.push(format!("previous {} {}", parent, path.display()));
+            }
+            output.push(format!("filename {}", path.display()));
+        }
+        output.push(format!("\t{}", content));
+    }
+
+    Ok(output)
+}
+
+/// Returns the blob hash and content of `path` in `commit_hash`, reading
+/// each version once.
+fn cached_version<'a>(
+    versions: &'a mut HashMap<Sha1Hash, Option<(Sha1Hash, Vec<u8>)>>,
+    path: &Path,
+    commit_hash: &Sha1Hash,
+    config: &Config,
+) -> Result<Option<&'a (Sha1Hash, Vec<u8>)>> {
+    if !versions.contains_key(commit_hash) {
+        let version = match file_version(path, commit_hash, config)? {
+            Some(sha1) => {
+                let content = read_blob(&sha1, config)?;
+                Some((sha1, content))
+            }
+            None => None,
+        };
+        let _ = versions.insert(commit_hash.clone(), version);
+    }
+
+    Ok(versions[commit_hash].as_ref())
+}
+
+/// Finds the blob `path` has in `commit_hash`, if it is a file there.
+fn file_version(path: &Path, commit_hash: &Sha1Hash, config: &Config) -> Result<Option<Sha1Hash>> {
+    let tree = GitObject::read_tree_ish(&config.obj_db_path, commit_hash)?;
+    Ok(tree
+        .entries
+        .get(path)
+        .filter(|entry| !entry.is_gitlink())
+        .map(|entry| entry.sha1().clone()))
+}
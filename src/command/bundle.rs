@@ -0,0 +1,209 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use tracing::debug;
+
+use crate::{
+    pack::{read_pack, verify_pack, write_pack},
+    refs,
+    revision::{parse_revision, resolve_ref_name},
+    walk::reachable_objects,
+    Config, GitError, GitObject, Result, Sha1Hash,
+};
+
+const BUNDLE_SIGNATURE: &str = "# baby-git bundle v1";
+
+/// The header of a bundle file: the commits a repository must already have
+/// to take the bundle, and the refs it carries.
+#[derive(Debug, Default)]
+pub struct Bundle {
+    pub prerequisites: Vec<Sha1Hash>,
+    pub refs: Vec<(String, Sha1Hash)>,
+}
+
+/// Writes a bundle of the refs named in `revs` to `path`: the signature
+/// line, a `-<hash> <subject>` line per prerequisite commit and a
+/// `<hash> <ref>` line per ref, a blank line, then a pack of the objects
+/// they reach. Objects reachable from revisions given as `^<rev>` or as the
+/// left side of `<rev>..<ref>` are left out; the commits the bundle then
+/// builds on become its prerequisites.
+pub fn bundle_create(path: &Path, revs: &[String], config: &Config) -> Result<Bundle> {
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    for rev in revs {
+        let name = if let Some(rev) = rev.strip_prefix('^') {
+            exclude.push(parse_revision(rev, config)?);
+            continue;
+        } else if let Some((left, right)) = rev.split_once("..") {
+            exclude.push(parse_revision(left, config)?);
+            if right.is_empty() {
+                "HEAD"
+            } else {
+                right
+            }
+        } else {
+            rev.as_str()
+        };
+        let full_name = resolve_ref_name(name, config)?
+            .ok_or_else(|| GitError::InvalidRefName(name.to_owned()))?;
+        let sha1 = parse_revision(&full_name, config)?;
+        if !include.iter().any(|(included, _)| included == &full_name) {
+            include.push((full_name, sha1));
+        }
+    }
+
+    let tips = include
+        .iter()
+        .map(|(_, sha1)| sha1.clone())
+        .collect::<Vec<_>>();
+    let objects = reachable_objects(&tips, &exclude, config)?;
+    if objects.is_empty() {
+        return Err(GitError::BundleError(
+            "refusing to create an empty bundle".to_owned(),
+        ));
+    }
+
+    let bundled = objects.iter().collect::<HashSet<_>>();
+    let mut prerequisites = Vec::new();
+    for sha1 in &objects {
+        if let GitObject::Commit(commit_obj) = GitObject::read_sha1_file(&config.obj_db_path, sha1)?
+        {
+            for parent in commit_obj.parents() {
+                if !bundled.contains(parent) && !prerequisites.contains(parent) {
+                    prerequisites.push(parent.clone());
+                }
+            }
+        }
+    }
+    debug!(
+        "bundling {} objects on {} prerequisites",
+        objects.len(),
+        prerequisites.len()
+    );
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{}", BUNDLE_SIGNATURE)?;
+    for sha1 in &prerequisites {
+        let commit_obj = GitObject::read_commit(&config.obj_db_path, sha1)?;
+        let subject = commit_obj.user_comment().lines().next().unwrap_or_default();
+        writeln!(writer, "-{} {}", sha1, subject)?;
+    }
+    for (name, sha1) in &include {
+        writeln!(writer, "{} {}", sha1, name)?;
+    }
+    writeln!(writer)?;
+    write_pack(&objects, &mut writer, config)?;
+
+    Ok(Bundle {
+        prerequisites,
+        refs: include,
+    })
+}
+
+/// Checks that the bundle at `path` is intact and that the repository has
+/// its prerequisites, returning its header.
+pub fn bundle_verify(path: &Path, config: &Config) -> Result<Bundle> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let bundle = read_header(&mut reader)?;
+    check_prerequisites(&bundle, config)?;
+    check_refs(&bundle, &verify_pack(reader)?, config)?;
+
+    Ok(bundle)
+}
+
+/// Stores the objects of the bundle at `path` and returns the refs it
+/// carries. The bundle is checked in full before anything is stored. Local
+/// refs are left untouched.
+pub fn bundle_unbundle(path: &Path, config: &Config) -> Result<Vec<(String, Sha1Hash)>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let bundle = read_header(&mut reader)?;
+    check_prerequisites(&bundle, config)?;
+    let objects = verify_pack(reader)?;
+    check_refs(&bundle, &objects, config)?;
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let _ = read_header(&mut reader)?;
+    if read_pack(reader, config)? != objects {
+        return Err(GitError::BundleError(
+            "changed while being unbundled".to_owned(),
+        ));
+    }
+
+    Ok(bundle.refs)
+}
+
+/// Whether `path` is a bundle file rather than a repository.
+pub fn is_bundle(path: &Path) -> bool {
+    let mut signature = vec![0; BUNDLE_SIGNATURE.len()];
+    path.is_file()
+        && File::open(path)
+            .and_then(|mut file| file.read_exact(&mut signature))
+            .is_ok()
+        && signature == BUNDLE_SIGNATURE.as_bytes()
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> Result<Bundle> {
+    let mut line = String::new();
+    let _ = reader.read_line(&mut line)?;
+    if line.trim_end() != BUNDLE_SIGNATURE {
+        return Err(GitError::BundleError("bad signature".to_owned()));
+    }
+
+    let mut bundle = Bundle::default();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(GitError::BundleError("truncated header".to_owned()));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some(prerequisite) = line.strip_prefix('-') {
+            let (sha1, _) = prerequisite.split_once(' ').unwrap_or((prerequisite, ""));
+            bundle.prerequisites.push(sha1.parse()?);
+        } else {
+            let (sha1, name) = line
+                .split_once(' ')
+                .ok_or_else(|| GitError::BundleError(format!("malformed line {:?}", line)))?;
+            refs::check_ref_name(name)?;
+            bundle.refs.push((name.to_owned(), sha1.parse()?));
+        }
+    }
+
+    Ok(bundle)
+}
+
+fn check_prerequisites(bundle: &Bundle, config: &Config) -> Result<()> {
+    let missing = bundle
+        .prerequisites
+        .iter()
+        .filter(|sha1| !GitObject::has_sha1_file(&config.obj_db_path, sha1))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(GitError::MissingPrerequisites(missing))
+    }
+}
+
+/// Fails unless the commit of every ref of the bundle is in its pack or
+/// already in the repository.
+fn check_refs(bundle: &Bundle, objects: &[Sha1Hash], config: &Config) -> Result<()> {
+    match bundle.refs.iter().find(|(_, sha1)| {
+        !objects.contains(sha1) && !GitObject::has_sha1_file(&config.obj_db_path, sha1)
+    }) {
+        Some((name, _)) => Err(GitError::BundleError(format!(
+            "{} is not in the pack",
+            name
+        ))),
+        None => Ok(()),
+    }
+}
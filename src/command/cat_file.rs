@@ -1,26 +1,114 @@
-use std::{io::Write, path::PathBuf};
+use std::{
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
 
-use tracing::error;
+use tracing::{debug, error};
 
 use super::tempfile;
-use crate::{Config, GitError, GitObject, Result, Sha1Hash};
+use crate::{
+    revision::parse_revision, Config, GitError, GitObject, ObjectStream, Result, Sha1Hash,
+};
 
+/// Writes the object into a temporary file in the current directory,
+/// returning its path and the object type.
 pub fn cat_file(obj_hash: Sha1Hash, config: &Config) -> Result<(PathBuf, String)> {
-    let obj = GitObject::read_sha1_file(&config.obj_db_path, &obj_hash)?;
+    let (tmp_path, tmp_file) = tempfile(Path::new(""))?;
+    let obj_type = cat_file_to(&obj_hash, None, tmp_file, config)?;
 
-    let (tmp_path, mut tmp_file) = tempfile()?;
-    match &obj {
-        GitObject::Blob(blob_obj) => {
-            tmp_file.write_all(blob_obj.as_ref())?;
+    Ok((tmp_path, obj_type.to_owned()))
+}
+
+/// Writes the content of the object to `writer`, returning its type. Blobs
+/// are copied as is, trees and commits as the text `cat-file -p` prints.
+/// Fails if `expected_type` is given and does not match.
+pub fn cat_file_to<W: Write>(
+    obj_hash: &Sha1Hash,
+    expected_type: Option<&str>,
+    mut writer: W,
+    config: &Config,
+) -> Result<&'static str> {
+    let obj = GitObject::open_sha1_file(&config.obj_db_path, obj_hash)?;
+    let obj_type = object_type(&obj);
+    if let Some(expected_type) = expected_type {
+        if expected_type != obj_type {
+            error!("{} is a {} object", obj_hash, obj_type);
+            return Err(GitError::WrongObjectType(
+                obj_hash.clone(),
+                expected_type.to_owned(),
+            ));
         }
-        GitObject::Commit(commit_obj) => {
-            tmp_file.write_all(commit_obj.to_string().as_bytes())?;
+    }
+
+    match obj {
+        ObjectStream::Blob(mut blob_reader) => {
+            let _ = io::copy(&mut blob_reader, &mut writer)?;
         }
-        GitObject::Tree(_) => {
-            error!("{} is a tree object", obj_hash);
-            return Err(GitError::IsTreeError(obj_hash));
+        ObjectStream::Other(obj) => writer.write_all(&object_text(&obj))?,
+    }
+
+    Ok(obj_type)
+}
+
+/// Returns the type and content size of the object without reading the
+/// content of blobs.
+pub fn cat_file_info(obj_hash: &Sha1Hash, config: &Config) -> Result<(&'static str, u64)> {
+    let obj = GitObject::open_sha1_file(&config.obj_db_path, obj_hash)?;
+    let obj_type = object_type(&obj);
+    let size = match obj {
+        ObjectStream::Blob(blob_reader) => blob_reader.size(),
+        ObjectStream::Other(obj) => object_text(&obj).len() as u64,
+    };
+
+    Ok((obj_type, size))
+}
+
+/// Reads one revision per line from `input` and writes `<hash> <type>
+/// <size>` for each, followed by the content and a newline when
+/// `with_contents` is set. Unknown objects are reported as `<rev> missing`.
+pub fn cat_file_batch<R: BufRead, W: Write>(
+    input: R,
+    mut output: W,
+    with_contents: bool,
+    config: &Config,
+) -> Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        let rev = line.trim();
+        let obj_hash = match parse_revision(rev, config) {
+            Ok(obj_hash) if GitObject::has_sha1_file(&config.obj_db_path, &obj_hash) => obj_hash,
+            _ => {
+                debug!("{} is missing", rev);
+                writeln!(output, "{} missing", rev)?;
+                output.flush()?;
+                continue;
+            }
+        };
+
+        let (obj_type, size) = cat_file_info(&obj_hash, config)?;
+        writeln!(output, "{} {} {}", obj_hash, obj_type, size)?;
+        if with_contents {
+            let _ = cat_file_to(&obj_hash, None, &mut output, config)?;
+            writeln!(output)?;
         }
+        output.flush()?;
     }
 
-    Ok((tmp_path, obj.to_string()))
+    Ok(())
+}
+
+fn object_type(obj: &ObjectStream) -> &'static str {
+    match obj {
+        ObjectStream::Blob(_) | ObjectStream::Other(GitObject::Blob(_)) => "blob",
+        ObjectStream::Other(GitObject::Tree(_)) => "tree",
+        ObjectStream::Other(GitObject::Commit(_)) => "commit",
+    }
+}
+
+fn object_text(obj: &GitObject) -> Vec<u8> {
+    match obj {
+        GitObject::Blob(blob_obj) => blob_obj.as_ref().to_vec(),
+        GitObject::Tree(tree_obj) => tree_obj.to_string().into_bytes(),
+        GitObject::Commit(commit_obj) => commit_obj.to_string().into_bytes(),
+    }
 }
@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use tracing::error;
+
+use crate::{ignore::normalize_path, worktree, Cache, CacheLock, Config, Result};
+
+/// Writes index entries to the work tree: the named `paths`, or every entry
+/// when `paths` is empty. Existing files are only overwritten with `force`.
+/// Returns the paths that were checked out.
+pub fn checkout_index(paths: Vec<PathBuf>, force: bool, config: &Config) -> Result<Vec<PathBuf>> {
+    let lock = CacheLock::acquire(&config.cache_path)?;
+    let mut cache = Cache::read_cache(&config.cache_path)?;
+
+    let paths = if paths.is_empty() {
+        cache.entries.keys().cloned().collect()
+    } else {
+        paths
+            .iter()
+            .map(|path| normalize_path(path))
+            .collect::<Vec<_>>()
+    };
+    worktree::check_paths(paths.iter())?;
+
+    let mut checked_out = Vec::new();
+    for path in paths {
+        let entry = match cache.entries.get(&path) {
+            Some(entry) => entry,
+            None => {
+                error!("{:?} is not in the cache", path);
+                continue;
+            }
+        };
+        if !force && path.symlink_metadata().is_ok() {
+            error!("{:?} already exists, no checkout", path);
+            continue;
+        }
+
+        worktree::checkout_file(&path, entry.st_mode, &entry.sha1, config)?;
+        let sha1 = entry.sha1.clone();
+        cache.insert(path.clone(), sha1)?;
+        checked_out.push(path);
+    }
+
+    lock.commit(&cache)?;
+    Ok(checked_out)
+}
@@ -0,0 +1,308 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use tracing::debug;
+
+use super::write_tree;
+use crate::{
+    merge::{merge_trees, read_blob},
+    refs::{self, RefTransaction},
+    worktree, Cache, Commit, Config, GitError, GitObject, Result, Sha1Hash, Tree,
+};
+
+const CHERRY_PICK_HEAD: &str = "CHERRY_PICK_HEAD";
+const REVERT_HEAD: &str = "REVERT_HEAD";
+const MERGE_MSG: &str = "MERGE_MSG";
+const CONFLICTS_HEADER: &str = "# Conflicts:";
+
+/// What applying a commit did.
+#[derive(Debug)]
+pub enum PickOutcome {
+    /// The change applied cleanly and was committed.
+    Committed(Sha1Hash),
+    /// The change conflicted; the listed paths hold conflict markers.
+    Conflicts(Vec<PathBuf>),
+}
+
+/// Applies the change `commit_hash` made relative to its first parent to the
+/// cache and work tree, and commits it with the same message and author. On
+/// conflicts, stops with the state `cherry_pick_continue` resumes from. A
+/// change `HEAD` already has is refused unless `allow_empty`.
+pub fn cherry_pick(
+    commit_hash: &Sha1Hash,
+    allow_empty: bool,
+    config: &Config,
+) -> Result<PickOutcome> {
+    start(CHERRY_PICK_HEAD, commit_hash, false, allow_empty, config)
+}
+
+/// Like `cherry_pick`, but applies the inverse of the change and commits it
+/// as `Revert "<subject>"`.
+pub fn revert(commit_hash: &Sha1Hash, allow_empty: bool, config: &Config) -> Result<PickOutcome> {
+    start(REVERT_HEAD, commit_hash, true, allow_empty, config)
+}
+
+/// Commits the resolved cache after a conflicted cherry-pick or revert.
+pub fn cherry_pick_continue(config: &Config) -> Result<Sha1Hash> {
+    let (state, commit_hash) = in_progress(config)?
+        .ok_or_else(|| GitError::NoOperation("cherry-pick or revert".to_owned()))?;
+    let message = fs::read_to_string(config.repo_path.join(MERGE_MSG))?;
+    let (message, conflicts) = match message.split_once(CONFLICTS_HEADER) {
+        Some((message, conflicts)) => (message.to_owned(), conflict_paths(conflicts)),
+        None => (message, Vec::new()),
+    };
+    check_resolved(&conflicts, config)?;
+
+    let picked = GitObject::read_commit(&config.obj_db_path, &commit_hash)?;
+    let author_info = if state == CHERRY_PICK_HEAD {
+        picked.author_info().to_owned()
+    } else {
+        config.author_info()
+    };
+    let tree_hash = write_tree(config)?;
+    let sha1 = commit_on_head(
+        tree_hash,
+        false,
+        author_info,
+        message,
+        reflog_action(state),
+        config,
+    )?;
+    clear_state(config)?;
+
+    Ok(sha1)
+}
+
+/// Gives up a conflicted cherry-pick or revert, resetting the cache and work
+/// tree to `HEAD`.
+pub fn cherry_pick_abort(config: &Config) -> Result<()> {
+    if in_progress(config)?.is_none() {
+        return Err(GitError::NoOperation("cherry-pick or revert".to_owned()));
+    }
+
+    let head = refs::read_ref(refs::HEAD, config)?
+        .ok_or_else(|| GitError::UnknownRevision(refs::HEAD.to_owned()))?;
+    let head_commit = GitObject::read_commit(&config.obj_db_path, &head)?;
+    worktree::checkout_tree(head_commit.tree(), config)?;
+
+    clear_state(config)
+}
+
+fn start(
+    state: &str,
+    commit_hash: &Sha1Hash,
+    revert: bool,
+    allow_empty: bool,
+    config: &Config,
+) -> Result<PickOutcome> {
+    if let Some((state, _)) = in_progress(config)? {
+        return Err(GitError::OperationInProgress(
+            reflog_action(state).to_owned(),
+        ));
+    }
+
+    let picked = GitObject::read_commit(&config.obj_db_path, commit_hash)?;
+    let subject = picked.user_comment().lines().next().unwrap_or_default();
+    let (author_info, message) = if revert {
+        let message = format!(
+            "Revert \"{}\"\n\nThis reverts commit {}.\n",
+            subject, commit_hash
+        );
+        (config.author_info(), message)
+    } else {
+        (
+            picked.author_info().to_owned(),
+            picked.user_comment().to_owned(),
+        )
+    };
+
+    match pick_commit(&picked, commit_hash, revert, config)? {
+        Ok(tree_hash) => {
+            if !allow_empty && head_tree(config)?.as_ref() == Some(&tree_hash) {
+                return Err(GitError::NothingToCommit);
+            }
+            let sha1 = commit_on_head(
+                tree_hash,
+                false,
+                author_info,
+                message,
+                reflog_action(state),
+                config,
+            )?;
+            Ok(PickOutcome::Committed(sha1))
+        }
+        Err(conflicts) => {
+            let mut merge_msg = format!("{}\n{}\n", message.trim_end(), CONFLICTS_HEADER);
+            for path in &conflicts {
+                merge_msg.push_str(&format!("#\t{}\n", path.display()));
+            }
+            fs::write(config.repo_path.join(MERGE_MSG), merge_msg)?;
+            fs::write(config.repo_path.join(state), format!("{}\n", commit_hash))?;
+            Ok(PickOutcome::Conflicts(conflicts))
+        }
+    }
+}
+
+/// Merges the change `picked` made (or its inverse) into `HEAD`, updating
+/// the cache and work tree. Returns the merged tree, or the conflicting
+/// paths, whose cache entries are left as they were.
+pub(crate) fn pick_commit(
+    picked: &Commit,
+    commit_hash: &Sha1Hash,
+    revert: bool,
+    config: &Config,
+) -> Result<std::result::Result<Sha1Hash, Vec<PathBuf>>> {
+    let parent_tree = match picked.parents().first() {
+        Some(parent) => GitObject::read_tree_ish(&config.obj_db_path, parent)?,
+        None => Tree::default(),
+    };
+    let picked_tree = GitObject::read_tree_ish(&config.obj_db_path, picked.tree())?;
+    let (base, theirs) = if revert {
+        (picked_tree, parent_tree)
+    } else {
+        (parent_tree, picked_tree)
+    };
+
+    let ours = match refs::read_ref(refs::HEAD, config)? {
+        Some(head) => GitObject::read_tree_ish(&config.obj_db_path, &head)?,
+        None => Tree::default(),
+    };
+    let subject = picked.user_comment().lines().next().unwrap_or_default();
+    let label = format!("{}... {}", &commit_hash.to_string()[..7], subject);
+
+    let outcome = merge_trees(&base, &ours, &theirs, ("HEAD", &label), config)?;
+    worktree::switch_tree(&ours, &outcome.tree, &outcome.conflicts, config)?;
+    if !outcome.conflicts.is_empty() {
+        debug!("{} conflicts", outcome.conflicts.len());
+        return Ok(Err(outcome.conflicts));
+    }
+
+    Ok(Ok(GitObject::from(outcome.tree).write_sha1_file(config)?))
+}
+
+/// Commits `tree_hash` on top of `HEAD` and advances it. With `amend`, the
+/// `HEAD` commit is replaced by one with the same parents instead.
+pub(crate) fn commit_on_head(
+    tree_hash: Sha1Hash,
+    amend: bool,
+    author_info: String,
+    message: String,
+    reflog_action: &str,
+    config: &Config,
+) -> Result<Sha1Hash> {
+    let head = refs::read_ref(refs::HEAD, config)?;
+    let parents = match &head {
+        Some(head) if amend => GitObject::read_commit(&config.obj_db_path, head)?
+            .parents()
+            .to_vec(),
+        _ => head.iter().cloned().collect(),
+    };
+    let commit_obj = Commit::new(
+        tree_hash,
+        parents,
+        author_info,
+        config.committer_info(),
+        message.clone(),
+    );
+    let sha1 = GitObject::from(commit_obj).write_sha1_file(config)?;
+
+    let summary = message.lines().next().unwrap_or_default();
+    let mut transaction = RefTransaction::new(&format!("{}: {}", reflog_action, summary));
+    transaction.update(refs::HEAD, head.unwrap_or_default(), sha1.clone());
+    transaction.commit(config)?;
+
+    Ok(sha1)
+}
+
+/// The tree of the `HEAD` commit, if there is one.
+fn head_tree(config: &Config) -> Result<Option<Sha1Hash>> {
+    match refs::read_ref(refs::HEAD, config)? {
+        Some(head) => Ok(Some(
+            GitObject::read_commit(&config.obj_db_path, &head)?
+                .tree()
+                .clone(),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Fails if a conflicting path still has conflict markers in the cache or
+/// was not staged again since the conflict.
+pub(crate) fn check_resolved(conflicts: &[PathBuf], config: &Config) -> Result<()> {
+    let cache = Cache::read_cache(&config.cache_path)?;
+    let mut unresolved = Vec::new();
+    for path in conflicts {
+        let resolved = match cache.entries.get(path) {
+            Some(entry) => {
+                let staged = config
+                    .work_dir
+                    .join(path)
+                    .symlink_metadata()
+                    .map(|meta| entry.match_stat(meta) == 0)
+                    .unwrap_or(false);
+                staged && !has_conflict_markers(&read_blob(&entry.sha1, config)?)
+            }
+            None => !config.work_dir.join(path).exists(),
+        };
+        if !resolved {
+            unresolved.push(path.clone());
+        }
+    }
+
+    if unresolved.is_empty() {
+        Ok(())
+    } else {
+        Err(GitError::UnresolvedConflicts(unresolved))
+    }
+}
+
+pub(crate) fn conflict_paths(list: &str) -> Vec<PathBuf> {
+    list.lines()
+        .filter_map(|line| line.strip_prefix("#\t"))
+        .map(PathBuf::from)
+        .collect()
+}
+
+fn has_conflict_markers(content: &[u8]) -> bool {
+    content
+        .split(|&c| c == b'\n')
+        .any(|line| line.starts_with(b"<<<<<<< ") || line.starts_with(b">>>>>>> "))
+}
+
+fn in_progress(config: &Config) -> Result<Option<(&'static str, Sha1Hash)>> {
+    for state in [CHERRY_PICK_HEAD, REVERT_HEAD] {
+        match fs::read_to_string(config.repo_path.join(state)) {
+            Ok(content) => return Ok(Some((state, content.trim_end().parse()?))),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(None)
+}
+
+fn clear_state(config: &Config) -> Result<()> {
+    for name in [CHERRY_PICK_HEAD, REVERT_HEAD, MERGE_MSG] {
+        remove_state_file(&config.repo_path.join(name))?;
+    }
+
+    Ok(())
+}
+
+fn remove_state_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn reflog_action(state: &str) -> &'static str {
+    if state == REVERT_HEAD {
+        "revert"
+    } else {
+        "cherry-pick"
+    }
+}
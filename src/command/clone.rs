@@ -0,0 +1,54 @@
+use tracing::debug;
+
+use crate::{
+    refs::{self, RefTransaction},
+    worktree, Config, GitObject, Result, Sha1Hash,
+};
+
+/// Sets up a new repository from the `fetched` refs of another: every ref
+/// under `refs/` is copied, `HEAD` points at the branch the remote `HEAD`
+/// is on, preferring the default branch, or is detached when no branch
+/// matches, and its tree is checked out. Returns the branch checked out.
+pub fn clone(fetched: &[(String, Sha1Hash)], config: &Config) -> Result<Option<String>> {
+    let mut transaction = RefTransaction::new("clone");
+    for (name, sha1) in fetched {
+        if name.starts_with(refs::REFS_PREFIX) {
+            transaction.update(name, Sha1Hash::default(), sha1.clone());
+        }
+    }
+    transaction.commit(config)?;
+
+    let branches = fetched
+        .iter()
+        .filter(|(name, _)| name.starts_with(refs::HEADS_PREFIX))
+        .collect::<Vec<_>>();
+    let remote_head = fetched
+        .iter()
+        .find(|(name, _)| name == refs::HEAD)
+        .map(|(_, sha1)| sha1);
+    let on_head = |sha1: &Sha1Hash| remote_head.map_or(true, |head| head == sha1);
+    let branch = branches
+        .iter()
+        .find(|(name, sha1)| name == refs::DEFAULT_BRANCH && on_head(sha1))
+        .or_else(|| branches.iter().find(|(_, sha1)| on_head(sha1)))
+        .map(|(name, _)| name.clone());
+
+    let head = match (&branch, remote_head) {
+        (Some(branch), _) => {
+            refs::write_symref(refs::HEAD, branch, config)?;
+            refs::read_ref(branch, config)?
+        }
+        (None, Some(head)) => {
+            refs::detach_head(head, "clone", config)?;
+            Some(head.clone())
+        }
+        (None, None) => None,
+    };
+    if let Some(head) = head {
+        let commit_obj = GitObject::read_commit(&config.obj_db_path, &head)?;
+        worktree::checkout_tree(commit_obj.tree(), config)?;
+        debug!("checked out {}", head);
+    }
+
+    Ok(branch)
+}
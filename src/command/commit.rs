@@ -0,0 +1,110 @@
+use std::{fs, process::Command};
+
+use tracing::debug;
+
+use super::write_tree;
+use crate::{
+    refs::{self, RefTransaction},
+    Commit, Config, GitError, GitObject, Result, Sha1Hash,
+};
+
+const COMMIT_EDITMSG: &str = "COMMIT_EDITMSG";
+const EDITMSG_HELP: &str = "\n# Please enter the commit message for your changes. Lines starting\n\
+                            # with '#' will be ignored, and an empty message aborts the commit.\n";
+
+/// Commits the index on top of `HEAD` and advances the branch `HEAD` points
+/// at, or `HEAD` itself when detached. Without a `message` the editor is
+/// opened. With `amend`, the `HEAD` commit is replaced by one with the same
+/// parents and author, keeping its message unless a new one is given.
+/// Commits that would not change the tree are refused unless `allow_empty`.
+pub fn commit(
+    message: Option<String>,
+    allow_empty: bool,
+    amend: bool,
+    config: &Config,
+) -> Result<Sha1Hash> {
+    let tree_hash = write_tree(config)?;
+    let head = refs::read_ref(refs::HEAD, config)?;
+    let head_commit = match &head {
+        Some(head) => Some(GitObject::read_commit(&config.obj_db_path, head)?),
+        None if amend => return Err(GitError::UnknownRevision(refs::HEAD.to_owned())),
+        None => None,
+    };
+
+    let (parents, author_info, reflog_action) = match (&head, &head_commit) {
+        (Some(_), Some(head_commit)) if amend => (
+            head_commit.parents().to_vec(),
+            head_commit.author_info().to_owned(),
+            "commit (amend)",
+        ),
+        (Some(head), Some(head_commit)) => {
+            if !allow_empty && head_commit.tree() == &tree_hash {
+                return Err(GitError::NothingToCommit);
+            }
+            (vec![head.clone()], config.author_info(), "commit")
+        }
+        _ => {
+            let empty_tree = GitObject::from(GitObject::new_tree()).hash_sha1_file()?;
+            if !allow_empty && tree_hash == empty_tree {
+                return Err(GitError::NothingToCommit);
+            }
+            (Vec::new(), config.author_info(), "commit (initial)")
+        }
+    };
+
+    let message = match message {
+        Some(message) => message,
+        None => {
+            let template = match &head_commit {
+                Some(head_commit) if amend => head_commit.user_comment(),
+                _ => "",
+            };
+            edit_message(template, config)?
+        }
+    };
+    let message = format!("{}\n", message.trim_end());
+    if message.trim().is_empty() {
+        return Err(GitError::EmptyCommitMessage);
+    }
+
+    let commit_obj = Commit::new(
+        tree_hash,
+        parents,
+        author_info,
+        config.committer_info(),
+        message.clone(),
+    );
+    let sha1 = GitObject::from(commit_obj).write_sha1_file(config)?;
+    debug!("sha1 = {}", sha1);
+
+    let summary = message.lines().next().unwrap_or_default();
+    let mut transaction = RefTransaction::new(&format!("{}: {}", reflog_action, summary));
+    transaction.update(refs::HEAD, head.unwrap_or_default(), sha1.clone());
+    transaction.commit(config)?;
+
+    Ok(sha1)
+}
+
+/// Lets the user write the message in `config.editor`, starting from
+/// `template`. Lines starting with `#` are dropped.
+pub(crate) fn edit_message(template: &str, config: &Config) -> Result<String> {
+    let path = config.repo_path.join(COMMIT_EDITMSG);
+    fs::write(&path, format!("{}{}", template, EDITMSG_HELP))?;
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", config.editor))
+        .arg(&config.editor)
+        .arg(&path)
+        .status()?;
+    if !status.success() {
+        return Err(GitError::EditorFailed(config.editor.clone()));
+    }
+
+    let message = fs::read_to_string(&path)?
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    Ok(message)
+}
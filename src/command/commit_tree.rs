@@ -15,8 +15,8 @@ pub fn commit_tree(
         buf
     };
 
-    let commit_obj = GitObject::new_commit(tree_hash, parents, user_comment, &config);
-    let sha1 = GitObject::from(commit_obj).write_sha1_file(&config.obj_db_path)?;
+    let commit_obj = GitObject::new_commit(tree_hash, parents, user_comment, config);
+    let sha1 = GitObject::from(commit_obj).write_sha1_file(config)?;
     debug!("sha1 = {}", sha1);
 
     Ok(sha1)
@@ -0,0 +1,26 @@
+use crate::{
+    diff::{detect_renames, diff_trees, RenameOptions, TreeChange},
+    Config, GitObject, Result, Sha1Hash, Tree,
+};
+
+/// Lists the paths that differ between two trees or commits' trees, or with
+/// no `old`, the paths of `new`. With `renames`, moved and copied files are
+/// paired as `detect_renames` does.
+pub fn diff_tree(
+    old: Option<&Sha1Hash>,
+    new: &Sha1Hash,
+    renames: Option<&RenameOptions>,
+    config: &Config,
+) -> Result<Vec<TreeChange>> {
+    let old_tree = match old {
+        Some(old) => GitObject::read_tree_ish(&config.obj_db_path, old)?,
+        None => Tree::default(),
+    };
+    let new_tree = GitObject::read_tree_ish(&config.obj_db_path, new)?;
+    let changes = diff_trees(&old_tree, &new_tree);
+
+    match renames {
+        Some(options) => detect_renames(changes, options, config),
+        None => Ok(changes),
+    }
+}
@@ -0,0 +1,69 @@
+use tracing::debug;
+
+use crate::{
+    refs::{self, RefSpec, RefTransaction},
+    walk::is_fast_forward,
+    Config, GitError, Result, Sha1Hash,
+};
+
+/// Points the local refs named by `specs` at the `fetched` remote refs they
+/// map from, returning a status report line per changed ref. Without
+/// `specs`, every fetched ref under `refs/` updates its local namesake.
+/// Non-fast-forward updates are rejected unless the refspec or `force`
+/// allows them, and so is updating the checked out branch; the rest are
+/// applied together.
+pub fn fetch(
+    fetched: &[(String, Sha1Hash)],
+    specs: &[RefSpec],
+    force: bool,
+    config: &Config,
+) -> Result<Vec<String>> {
+    let specs = if specs.is_empty() {
+        fetched
+            .iter()
+            .filter(|(name, _)| name.starts_with(refs::REFS_PREFIX))
+            .map(|(name, _)| RefSpec {
+                src: Some(name.clone()),
+                dst: name.clone(),
+                force: false,
+            })
+            .collect()
+    } else {
+        specs.to_vec()
+    };
+
+    let checked_out = refs::resolve_ref(refs::HEAD, config)?;
+    let mut report = Vec::new();
+    let mut transaction = RefTransaction::new("fetch");
+    for spec in &specs {
+        let src = match &spec.src {
+            Some(src) => src,
+            None => return Err(GitError::InvalidRefName(format!(":{}", spec.dst))),
+        };
+        let new = fetched
+            .iter()
+            .find(|(name, _)| name == src)
+            .map(|(_, sha1)| sha1.clone())
+            .ok_or_else(|| GitError::InvalidRefName(src.clone()))?;
+        let old = refs::read_ref(&spec.dst, config)?.unwrap_or_default();
+
+        if old == new {
+            debug!("{} is up to date", spec.dst);
+            continue;
+        }
+        if spec.dst == checked_out {
+            report.push(format!("ng {} checked out", spec.dst));
+            continue;
+        }
+        if !force && !spec.force && !old.is_null() && !is_fast_forward(&old, &new, config)? {
+            report.push(format!("ng {} non-fast-forward", spec.dst));
+            continue;
+        }
+
+        transaction.update(&spec.dst, old, new);
+        report.push(format!("ok {}", spec.dst));
+    }
+    transaction.commit(config)?;
+
+    Ok(report)
+}
@@ -0,0 +1,66 @@
+use std::io::{Read, Write};
+
+use tracing::debug;
+
+use crate::{
+    pack::read_pack,
+    refs,
+    transport::{read_pkt_line, read_ref_advertisement, write_flush, write_pkt_line},
+    walk::reachable_commits,
+    Config, GitError, GitObject, Result, Sha1Hash,
+};
+
+/// Fetches the objects of the named remote refs (all refs when `names` is
+/// empty) and returns the fetched refs. Local refs are left untouched.
+pub fn fetch_pack<R: Read, W: Write>(
+    names: &[String],
+    mut input: R,
+    mut output: W,
+    config: &Config,
+) -> Result<Vec<(String, Sha1Hash)>> {
+    let remote_refs = read_ref_advertisement(&mut input)?;
+    let selected = if names.is_empty() {
+        remote_refs
+    } else {
+        names
+            .iter()
+            .map(|name| {
+                remote_refs
+                    .iter()
+                    .find(|(remote_name, _)| remote_name == name)
+                    .cloned()
+                    .ok_or_else(|| GitError::InvalidRefName(name.clone()))
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let mut wants = Vec::new();
+    for (_, sha1) in &selected {
+        if !GitObject::has_sha1_file(&config.obj_db_path, sha1) && !wants.contains(sha1) {
+            write_pkt_line(&mut output, &format!("want {}", sha1))?;
+            wants.push(sha1.clone());
+        }
+    }
+    write_flush(&mut output)?;
+    if wants.is_empty() {
+        return Ok(selected);
+    }
+
+    let tips = refs::list_refs(config)?
+        .into_iter()
+        .map(|(_, sha1)| sha1)
+        .collect::<Vec<_>>();
+    for have in reachable_commits(&tips, config)? {
+        write_pkt_line(&mut output, &format!("have {}", have))?;
+    }
+    write_pkt_line(&mut output, "done")?;
+    output.flush()?;
+
+    while let Some(line) = read_pkt_line(&mut input)? {
+        debug!("{}", line);
+    }
+    let objects = read_pack(&mut input, config)?;
+    debug!("received {} objects", objects.len());
+
+    Ok(selected)
+}
@@ -0,0 +1,114 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use clap::crate_version;
+use tracing::debug;
+
+use super::rebase::unique_commits;
+use crate::{
+    diff::{diff_trees, format_stats, tree_diff_patches, tree_diff_stats, StatFormat},
+    ident_date, split_ident, Config, GitError, GitObject, Result, Sha1Hash, Tree,
+};
+
+/// The date of the `From` line starting each mail, as git writes it.
+const MBOX_DATE: &str = "Mon Sep 17 00:00:00 2001";
+/// The longest file name slug taken from a subject.
+const MAX_SLUG_LENGTH: usize = 52;
+
+/// Writes a patch file for each commit from `upstream` to `head`, oldest
+/// first, into `output_dir`. Each is a mail in mbox format with the commit's
+/// author, date and message, a diff stat and the diff against its parent.
+/// Merge commits are left out. Fails without writing anything if a commit
+/// changes a binary file, which `apply` could not take back. Returns the
+/// paths written.
+pub fn format_patch(
+    upstream: &Sha1Hash,
+    head: &Sha1Hash,
+    output_dir: &Path,
+    config: &Config,
+) -> Result<Vec<PathBuf>> {
+    let commits = unique_commits(head, upstream, config)?;
+
+    let mut mails = Vec::new();
+    for (i, sha1) in commits.iter().enumerate() {
+        let commit_obj = GitObject::read_commit(&config.obj_db_path, sha1)?;
+        let parent_tree = match commit_obj.parents().first() {
+            Some(parent) => GitObject::read_tree_ish(&config.obj_db_path, parent)?,
+            None => Tree::default(),
+        };
+        let tree = GitObject::read_tree_ish(&config.obj_db_path, commit_obj.tree())?;
+        let changes = diff_trees(&parent_tree, &tree);
+        let patches = tree_diff_patches(&changes, config)?;
+        if let Some(patch) = patches.iter().find(|patch| patch.binary) {
+            return Err(GitError::BinaryChange(
+                sha1.clone(),
+                patch.path().to_owned(),
+            ));
+        }
+
+        let mut message = commit_obj.user_comment().lines();
+        let subject = message.next().unwrap_or_default();
+        let body = message.collect::<Vec<_>>().join("\n");
+        let (name, email, _) = split_ident(commit_obj.author_info());
+        let numbering = if commits.len() == 1 {
+            "PATCH".to_owned()
+        } else {
+            format!("PATCH {}/{}", i + 1, commits.len())
+        };
+
+        let mut mail = Vec::new();
+        writeln!(mail, "From {} {}", sha1, MBOX_DATE)?;
+        writeln!(mail, "From: {} <{}>", name, email)?;
+        if let Some(date) = ident_date(commit_obj.author_info()) {
+            writeln!(mail, "Date: {}", date.to_rfc2822())?;
+        }
+        writeln!(mail, "Subject: [{}] {}", numbering, subject)?;
+        writeln!(mail, "MIME-Version: 1.0")?;
+        writeln!(mail, "Content-Type: text/plain; charset=UTF-8")?;
+        writeln!(mail, "Content-Transfer-Encoding: 8bit\n")?;
+        if !body.trim().is_empty() {
+            writeln!(mail, "{}", body.trim())?;
+        }
+        writeln!(mail, "---")?;
+        for line in format_stats(&tree_diff_stats(&changes, config)?, StatFormat::Stat) {
+            writeln!(mail, "{}", line)?;
+        }
+        writeln!(mail)?;
+        for patch in &patches {
+            patch.write_to(&mut mail)?;
+        }
+        writeln!(mail, "-- \nbaby-git {}\n", crate_version!())?;
+
+        let path = output_dir.join(format!("{:04}-{}.patch", i + 1, slug(subject)));
+        debug!("{} -> {:?}", sha1, path);
+        mails.push((path, mail));
+    }
+
+    fs::create_dir_all(output_dir)?;
+    let mut paths = Vec::new();
+    for (path, mail) in mails {
+        fs::write(&path, mail)?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+/// Turns a subject into a file name: runs of characters other than letters,
+/// digits, `.` and `_` become a single `-`.
+fn slug(subject: &str) -> String {
+    let mut slug = String::new();
+    for c in subject.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(MAX_SLUG_LENGTH);
+
+    slug.trim_end_matches(['-', '.']).to_owned()
+}
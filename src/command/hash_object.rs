@@ -0,0 +1,45 @@
+use std::io::{Read, Seek};
+
+use tracing::debug;
+
+use crate::{Blob, Commit, Config, GitError, GitObject, Result, Sha1Hash, Tree};
+
+/// Computes the hash of an object of type `obj_type` whose content is read
+/// from `reader`, storing it in the database when `write` is set. Blobs of a
+/// known `size` are streamed; trees and commits are parsed from the text
+/// `cat-file -p` prints.
+pub fn hash_object<R: Read + Seek>(
+    mut reader: R,
+    size: Option<u64>,
+    obj_type: &str,
+    write: bool,
+    config: &Config,
+) -> Result<Sha1Hash> {
+    let obj = match (obj_type, size) {
+        ("blob", Some(size)) if write => return GitObject::write_blob_stream(reader, size, config),
+        ("blob", Some(size)) => return GitObject::hash_blob_stream(reader, size),
+        ("blob", None) => {
+            let mut bytes = Vec::new();
+            let _ = reader.read_to_end(&mut bytes)?;
+            GitObject::Blob(Blob::new(bytes))
+        }
+        ("tree", _) | ("commit", _) => {
+            let mut text = String::new();
+            let _ = reader.read_to_string(&mut text)?;
+            if obj_type == "tree" {
+                GitObject::Tree(text.parse::<Tree>()?)
+            } else {
+                GitObject::Commit(text.parse::<Commit>()?)
+            }
+        }
+        _ => return Err(GitError::ParseObjectError(obj_type.to_owned())),
+    };
+
+    if write {
+        let sha1 = obj.write_sha1_file(config)?;
+        debug!("wrote {} {}", obj_type, sha1);
+        Ok(sha1)
+    } else {
+        obj.hash_sha1_file()
+    }
+}
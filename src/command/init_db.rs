@@ -1,6 +1,6 @@
-use std::fs::{create_dir, File};
+use std::fs::{create_dir, create_dir_all, File};
 
-use crate::{Cache, Config, Result};
+use crate::{refs, Cache, Config, Result};
 
 pub fn init_db(config: &Config) -> Result<()> {
     create_dir(&config.repo_path)?;
@@ -11,6 +11,9 @@ pub fn init_db(config: &Config) -> Result<()> {
         create_dir(dir)?;
     }
 
+    create_dir_all(config.repo_path.join(refs::HEADS_PREFIX))?;
+    refs::write_symref(refs::HEAD, refs::DEFAULT_BRANCH, config)?;
+
     let mut cache = File::create(&config.cache_path)?;
     bincode::serialize_into(&mut cache, &Cache::default())?;
 
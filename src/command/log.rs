@@ -0,0 +1,113 @@
+use std::{
+    collections::{BinaryHeap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use tracing::debug;
+
+use crate::{
+    diff::{detect_renames, diff_trees, RenameOptions, TreeChange},
+    ident_timestamp, Commit, Config, GitObject, Result, Sha1Hash, Tree,
+};
+
+/// What `log` lists. With `path`, only commits that change it, a file or a
+/// directory, are listed; with `follow` as well, the file is tracked back
+/// across renames. `changes` asks for the changes of each commit, and
+/// `renames` for renames to be detected among them, which `follow` implies.
+#[derive(Clone, Debug, Default)]
+pub struct LogOptions {
+    pub path: Option<PathBuf>,
+    pub follow: bool,
+    pub changes: bool,
+    pub renames: bool,
+    pub max_count: Option<usize>,
+}
+
+/// A commit `log` lists, with the changes it made relative to its first
+/// parent when they were asked for, limited to the path when one is given.
+#[derive(Debug)]
+pub struct LogEntry {
+    pub sha1: Sha1Hash,
+    pub commit: Commit,
+    pub changes: Vec<TreeChange>,
+}
+
+/// Lists the commits reachable from `commit_hash`, newest first by commit
+/// date.
+pub fn log(commit_hash: &Sha1Hash, options: &LogOptions, config: &Config) -> Result<Vec<LogEntry>> {
+    let mut path = options.path.clone();
+    let mut entries = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = BinaryHeap::new();
+    queue.push((0, commit_hash.clone()));
+
+    while let Some((_, sha1)) = queue.pop() {
+        if !seen.insert(sha1.clone()) {
+            continue;
+        }
+        if options.max_count.is_some_and(|max| entries.len() >= max) {
+            break;
+        }
+
+        let commit_obj = GitObject::read_commit(&config.obj_db_path, &sha1)?;
+        for parent in commit_obj.parents() {
+            let parent_obj = GitObject::read_commit(&config.obj_db_path, parent)?;
+            queue.push((ident_timestamp(parent_obj.committer_info()), parent.clone()));
+        }
+
+        if path.is_none() && !options.changes {
+            entries.push(LogEntry {
+                sha1,
+                commit: commit_obj,
+                changes: Vec::new(),
+            });
+            continue;
+        }
+
+        let parent_tree = match commit_obj.parents().first() {
+            Some(parent) => GitObject::read_tree_ish(&config.obj_db_path, parent)?,
+            None => Tree::default(),
+        };
+        let tree = GitObject::read_tree_ish(&config.obj_db_path, commit_obj.tree())?;
+        let mut changes = diff_trees(&parent_tree, &tree);
+        // Comparing contents is slow, so renames are only looked for when
+        // asked.
+        if options.renames || options.follow {
+            changes = detect_renames(changes, &RenameOptions::default(), config)?;
+        }
+
+        if let Some(current) = &path {
+            changes.retain(|change| touches(change, current, options.follow));
+            if changes.is_empty() {
+                continue;
+            }
+            if options.follow {
+                if let Some(TreeChange::Renamed { old, .. }) = changes
+                    .iter()
+                    .find(|change| matches!(change, TreeChange::Renamed { .. }))
+                {
+                    debug!("following {:?} to {:?}", current, old.name());
+                    path = Some(old.name().to_owned());
+                }
+            }
+        }
+
+        entries.push(LogEntry {
+            sha1,
+            commit: commit_obj,
+            changes,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Whether `change` involves `path`, or with `follow`, ends at that exact
+/// file.
+fn touches(change: &TreeChange, path: &Path, follow: bool) -> bool {
+    if follow {
+        return change.path() == path;
+    }
+
+    change.path().starts_with(path) || change.old_path().is_some_and(|old| old.starts_with(path))
+}
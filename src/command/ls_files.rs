@@ -0,0 +1,88 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use crate::{ignore::IgnoreRules, worktree, Cache, CacheEntry, Config, Result, GITLINK_MODE};
+
+/// The groups of paths `ls_files` lists. Cached paths are listed when no
+/// group is selected.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LsFilesFilter {
+    pub cached: bool,
+    pub deleted: bool,
+    pub modified: bool,
+    pub others: bool,
+}
+
+/// Lists the paths in each selected group, cached, deleted, modified and
+/// others in that order. With `stage`, cache entries are prefixed with
+/// their mode, hash and stage number, which is always 0.
+pub fn ls_files(filter: LsFilesFilter, stage: bool, config: &Config) -> Result<Vec<String>> {
+    let cache = Cache::read_cache(&config.cache_path)?;
+    let cached = filter.cached || !(filter.deleted || filter.modified || filter.others);
+    let format = |entry: &CacheEntry| {
+        if stage {
+            format!(
+                "{:06o} {} 0\t{}",
+                entry.st_mode,
+                entry.sha1,
+                entry.name.display()
+            )
+        } else {
+            entry.name.display().to_string()
+        }
+    };
+
+    let mut lines = Vec::new();
+    if cached {
+        lines.extend(cache.entries.values().map(format));
+    }
+
+    if filter.deleted || filter.modified {
+        let mut deleted = Vec::new();
+        let mut modified = Vec::new();
+        for entry in cache.entries.values() {
+            let path = config.work_dir.join(&entry.name);
+            let meta = match path.symlink_metadata() {
+                Ok(meta) => meta,
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    deleted.push(format(entry));
+                    modified.push(format(entry));
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            let changed = if entry.st_mode == GITLINK_MODE {
+                worktree::gitlink_head(&path)?.as_ref() != Some(&entry.sha1)
+            } else {
+                entry.match_stat(meta) != 0
+            };
+            if changed {
+                modified.push(format(entry));
+            }
+        }
+
+        if filter.deleted {
+            lines.append(&mut deleted);
+        }
+        if filter.modified {
+            lines.append(&mut modified);
+        }
+    }
+
+    if filter.others {
+        let paths =
+            IgnoreRules::from_config(config)?.expand_paths(vec![config.work_dir.clone()])?;
+        lines.extend(
+            paths
+                .into_iter()
+                .map(|path| match path.strip_prefix(&config.work_dir) {
+                    Ok(name) => name.to_owned(),
+                    Err(_) => path,
+                })
+                .filter(|name: &PathBuf| !cache.entries.contains_key(name))
+                .map(|name| name.display().to_string()),
+        );
+    }
+
+    Ok(lines)
+}
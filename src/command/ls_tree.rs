@@ -0,0 +1,84 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    path::{Path, PathBuf},
+};
+
+use crate::{Config, GitObject, Result, Sha1Hash, Tree, TreeEntry, DIRECTORY_MODE};
+
+/// Lists the tree `tree_ish` (a tree or a commit) one directory level at a
+/// time like Git's nested trees, or every file with `recursive`, in which
+/// case `show_trees` also lists the directories walked through. A `path`
+/// restricts the listing to that entry, or to the contents of the directory
+/// if it ends with `/`. Trees are flat, so a directory is listed with the
+/// hash its entries would have as a tree of their own.
+pub fn ls_tree(
+    tree_ish: &Sha1Hash,
+    path: Option<&str>,
+    recursive: bool,
+    show_trees: bool,
+    config: &Config,
+) -> Result<Tree> {
+    let tree_obj = GitObject::read_tree_ish(&config.obj_db_path, tree_ish)?;
+
+    let (base, only) = match path {
+        None => (PathBuf::new(), None),
+        Some(path) if path.ends_with('/') => (PathBuf::from(path), None),
+        Some(path) => {
+            let path = PathBuf::from(path);
+            let base = path.parent().map(Path::to_owned).unwrap_or_default();
+            (base, Some(path))
+        }
+    };
+
+    let mut listing = BTreeMap::new();
+    for (name, entry) in &tree_obj.entries {
+        let rel_path = match name.strip_prefix(&base) {
+            Ok(rel_path) => rel_path,
+            Err(_) => continue,
+        };
+        let child = match rel_path.components().next() {
+            Some(component) => base.join(component),
+            None => continue,
+        };
+        if only.as_ref().is_some_and(|only| only != &child) {
+            continue;
+        }
+
+        if &child == name {
+            let _ = listing.insert(name.clone(), entry.clone());
+        } else if !recursive {
+            if let Entry::Vacant(vacant) = listing.entry(child) {
+                let entry = dir_entry(&tree_obj, vacant.key())?;
+                let _ = vacant.insert(entry);
+            }
+        } else {
+            if show_trees {
+                for dir in name.ancestors().skip(1) {
+                    if !dir.starts_with(&child) {
+                        break;
+                    }
+                    if !listing.contains_key(dir) {
+                        let _ = listing.insert(dir.to_owned(), dir_entry(&tree_obj, dir)?);
+                    }
+                }
+            }
+            let _ = listing.insert(name.clone(), entry.clone());
+        }
+    }
+
+    Ok(Tree { entries: listing })
+}
+
+fn dir_entry(tree_obj: &Tree, dir: &Path) -> Result<TreeEntry> {
+    let mut subtree = Tree::default();
+    for (name, entry) in &tree_obj.entries {
+        if let Ok(rel_path) = name.strip_prefix(dir) {
+            let rel_entry =
+                TreeEntry::new(entry.st_mode(), rel_path.to_owned(), entry.sha1().clone());
+            let _ = subtree.entries.insert(rel_path.to_owned(), rel_entry);
+        }
+    }
+    let sha1 = GitObject::Tree(subtree).hash_sha1_file()?;
+
+    Ok(TreeEntry::new(DIRECTORY_MODE, dir.to_owned(), sha1))
+}
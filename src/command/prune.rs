@@ -0,0 +1,78 @@
+use std::{
+    collections::HashSet,
+    fs,
+    time::{Duration, SystemTime},
+};
+
+use tracing::debug;
+
+use crate::{
+    reflog, refs, walk::reachable_objects, Cache, Config, GitObject, Result, Sha1Hash, GITLINK_MODE,
+};
+
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    pub pruned: Vec<Sha1Hash>,
+    pub reclaimed: u64,
+}
+
+/// Deletes loose objects that are unreachable from refs, `HEAD`, the index
+/// and reflogs and were last modified more than `grace_period` ago.
+pub fn prune(grace_period: Duration, dry_run: bool, config: &Config) -> Result<PruneReport> {
+    let reachable = reachable_objects(&prune_roots(config)?, &[], config)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    debug!("{} reachable objects", reachable.len());
+
+    let now = SystemTime::now();
+    let mut report = PruneReport::default();
+    for sha1 in GitObject::list_sha1_files(&config.obj_db_path)? {
+        if reachable.contains(&sha1) {
+            continue;
+        }
+
+        let obj_path = config.obj_db_path.join(sha1.sha1_file_name());
+        let meta = fs::metadata(&obj_path)?;
+        let age = now.duration_since(meta.modified()?).unwrap_or_default();
+        if age < grace_period {
+            debug!("keeping recent object {}", sha1);
+            continue;
+        }
+
+        if !dry_run {
+            fs::remove_file(&obj_path)?;
+        }
+        report.reclaimed += meta.len();
+        report.pruned.push(sha1);
+    }
+
+    Ok(report)
+}
+
+fn prune_roots(config: &Config) -> Result<Vec<Sha1Hash>> {
+    let mut roots = refs::list_refs(config)?
+        .into_iter()
+        .map(|(_, sha1)| sha1)
+        .collect::<Vec<_>>();
+    roots.extend(refs::read_ref(refs::HEAD, config)?);
+    for name in reflog::list_reflogs(config)? {
+        for entry in reflog::read_reflog(&name, config)? {
+            roots.extend(
+                vec![entry.old, entry.new]
+                    .into_iter()
+                    .filter(|sha1| GitObject::has_sha1_file(&config.obj_db_path, sha1)),
+            );
+        }
+    }
+
+    let cache = Cache::read_cache(&config.cache_path)?;
+    roots.extend(
+        cache
+            .entries
+            .into_values()
+            .filter(|entry| entry.st_mode != GITLINK_MODE)
+            .map(|entry| entry.sha1),
+    );
+
+    Ok(roots)
+}
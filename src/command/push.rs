@@ -0,0 +1,32 @@
+use std::io::{Read, Write};
+
+use crate::{
+    refs::{self, RefSpec},
+    send_pack, Config, Result,
+};
+
+/// Pushes `specs` to the remote, defaulting to the current branch. `force`
+/// allows non-fast-forward updates for every refspec.
+pub fn push<R: Read, W: Write>(
+    mut specs: Vec<RefSpec>,
+    force: bool,
+    input: R,
+    output: W,
+    config: &Config,
+) -> Result<Vec<String>> {
+    if specs.is_empty() {
+        let branch = refs::resolve_ref(refs::HEAD, config)?;
+        specs.push(RefSpec {
+            src: Some(branch.clone()),
+            dst: branch,
+            force: false,
+        });
+    }
+    if force {
+        for spec in &mut specs {
+            spec.force = true;
+        }
+    }
+
+    send_pack(&specs, input, output, config)
+}
@@ -0,0 +1,469 @@
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    slice,
+    str::FromStr,
+};
+
+use tracing::debug;
+
+use super::{
+    cherry_pick::{check_resolved, commit_on_head, pick_commit},
+    commit::edit_message,
+    ls_files, write_tree, LsFilesFilter,
+};
+use crate::{
+    reflog, refs, revision::parse_revision, walk::reachable_commits, worktree, Config, GitError,
+    GitObject, Result, Sha1Hash,
+};
+
+const REBASE_DIR: &str = "rebase-merge";
+const HEAD_NAME: &str = "head-name";
+const ORIG_HEAD: &str = "orig-head";
+const ONTO: &str = "onto";
+const TODO: &str = "todo";
+const CURRENT: &str = "current";
+const CONFLICTS: &str = "conflicts";
+const DETACHED: &str = "detached HEAD";
+
+/// Where a rebase got to.
+#[derive(Debug)]
+pub enum RebaseOutcome {
+    /// Every step was applied; holds the new `HEAD`.
+    Finished(Sha1Hash),
+    /// A step conflicted; the listed paths hold conflict markers.
+    Stopped(Vec<PathBuf>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    Pick,
+    Reword,
+    Squash,
+    Drop,
+}
+
+/// A line of the todo list: an action and the commit it applies to.
+#[derive(Debug)]
+struct Step {
+    action: Action,
+    commit: Sha1Hash,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Action::Pick => "pick",
+            Action::Reword => "reword",
+            Action::Squash => "squash",
+            Action::Drop => "drop",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Action {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pick" | "p" => Ok(Action::Pick),
+            "reword" | "r" => Ok(Action::Reword),
+            "squash" | "s" => Ok(Action::Squash),
+            "drop" | "d" => Ok(Action::Drop),
+            _ => Err(GitError::ParseTodoError(s.to_owned())),
+        }
+    }
+}
+
+/// Replays the commits of the current branch that `upstream` does not have
+/// onto `upstream`, one by one, then points the branch at the result. With
+/// `todo_file`, the steps are read from it instead: one `pick`, `reword`,
+/// `squash` or `drop` and a revision per line. Stops on conflicts with the
+/// state `rebase_continue`, `rebase_skip` and `rebase_abort` work from.
+pub fn rebase(upstream: &str, todo_file: Option<&Path>, config: &Config) -> Result<RebaseOutcome> {
+    let state_dir = config.repo_path.join(REBASE_DIR);
+    if state_dir.exists() {
+        return Err(GitError::OperationInProgress("rebase".to_owned()));
+    }
+
+    let onto = parse_revision(upstream, config)?;
+    let onto_commit = GitObject::read_commit(&config.obj_db_path, &onto)?;
+    let head = refs::read_ref(refs::HEAD, config)?
+        .ok_or_else(|| GitError::UnknownRevision(refs::HEAD.to_owned()))?;
+    let head_commit = GitObject::read_commit(&config.obj_db_path, &head)?;
+    check_clean(head_commit.tree(), config)?;
+
+    let steps = match todo_file {
+        Some(path) => parse_todo(&fs::read_to_string(path)?, config)?,
+        None => unique_commits(&head, &onto, config)?
+            .into_iter()
+            .map(|commit| Step {
+                action: Action::Pick,
+                commit,
+            })
+            .collect(),
+    };
+    if let Some(step) = steps.iter().find(|step| step.action != Action::Drop) {
+        if step.action == Action::Squash {
+            return Err(GitError::ParseTodoError(format!(
+                "{} {}",
+                step.action, step.commit
+            )));
+        }
+    }
+
+    let head_name = refs::read_symref(refs::HEAD, config)?.unwrap_or_else(|| DETACHED.to_owned());
+    fs::create_dir(&state_dir)?;
+    fs::write(state_dir.join(HEAD_NAME), format!("{}\n", head_name))?;
+    fs::write(state_dir.join(ORIG_HEAD), format!("{}\n", head))?;
+    fs::write(state_dir.join(ONTO), format!("{}\n", onto))?;
+    write_todo(&steps, config)?;
+
+    // Without the checkout there is nothing to continue from.
+    let current = GitObject::read_tree_ish(&config.obj_db_path, head_commit.tree())?;
+    let to = GitObject::read_tree_ish(&config.obj_db_path, onto_commit.tree())?;
+    if let Err(e) = worktree::switch_tree(&current, &to, &[], config) {
+        fs::remove_dir_all(&state_dir)?;
+        return Err(e);
+    }
+    refs::detach_head(
+        &onto,
+        &format!("rebase (start): checkout {}", upstream),
+        config,
+    )?;
+
+    run(config)
+}
+
+/// Commits the resolved cache for the step that stopped, then carries on.
+pub fn rebase_continue(config: &Config) -> Result<RebaseOutcome> {
+    check_in_progress(config)?;
+
+    let current = parse_todo(&read_state(CURRENT, config)?.unwrap_or_default(), config)?;
+    if let Some(step) = current.first() {
+        let conflicts = read_state(CONFLICTS, config)?
+            .map(|list| list.lines().map(PathBuf::from).collect::<Vec<_>>())
+            .unwrap_or_default();
+        check_resolved(&conflicts, config)?;
+
+        let tree_hash = write_tree(config)?;
+        commit_step(step, tree_hash, config)?;
+        clear_step(config)?;
+    }
+
+    run(config)
+}
+
+/// Leaves out the step that stopped, resetting the cache and work tree to
+/// `HEAD`, then carries on.
+pub fn rebase_skip(config: &Config) -> Result<RebaseOutcome> {
+    check_in_progress(config)?;
+
+    let head = refs::read_ref(refs::HEAD, config)?
+        .ok_or_else(|| GitError::UnknownRevision(refs::HEAD.to_owned()))?;
+    let head_commit = GitObject::read_commit(&config.obj_db_path, &head)?;
+    worktree::checkout_tree(head_commit.tree(), config)?;
+    clear_step(config)?;
+
+    run(config)
+}
+
+/// Gives up the rebase, returning the cache, work tree and `HEAD` to where
+/// they were before it started.
+pub fn rebase_abort(config: &Config) -> Result<()> {
+    check_in_progress(config)?;
+
+    let orig_head = read_state(ORIG_HEAD, config)?
+        .unwrap_or_default()
+        .parse::<Sha1Hash>()?;
+    let head_name = read_state(HEAD_NAME, config)?.unwrap_or_default();
+    let orig_commit = GitObject::read_commit(&config.obj_db_path, &orig_head)?;
+    worktree::checkout_tree(orig_commit.tree(), config)?;
+
+    if head_name == DETACHED {
+        refs::detach_head(
+            &orig_head,
+            &format!("rebase (abort): returning to {}", orig_head),
+            config,
+        )?;
+    } else {
+        let head = refs::read_ref(refs::HEAD, config)?.unwrap_or_default();
+        refs::write_symref(refs::HEAD, &head_name, config)?;
+        reflog::append_reflog(
+            refs::HEAD,
+            &head,
+            &orig_head,
+            &format!("rebase (abort): returning to {}", head_name),
+            config,
+        )?;
+    }
+
+    fs::remove_dir_all(config.repo_path.join(REBASE_DIR))?;
+    Ok(())
+}
+
+/// Applies the remaining steps of the todo list, stopping at the first one
+/// that conflicts.
+fn run(config: &Config) -> Result<RebaseOutcome> {
+    loop {
+        let mut steps = parse_todo(&read_state(TODO, config)?.unwrap_or_default(), config)?;
+        if steps.is_empty() {
+            return finish(config);
+        }
+        let step = steps.remove(0);
+        write_todo(&steps, config)?;
+        if step.action == Action::Drop {
+            debug!("dropping {}", step.commit);
+            continue;
+        }
+
+        let state_dir = config.repo_path.join(REBASE_DIR);
+        fs::write(
+            state_dir.join(CURRENT),
+            format!("{} {}\n", step.action, step.commit),
+        )?;
+        let outcome = GitObject::read_commit(&config.obj_db_path, &step.commit)
+            .and_then(|picked| pick_commit(&picked, &step.commit, false, config));
+        let outcome = match outcome {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                // Nothing was applied, so the step stays to be retried.
+                clear_step(config)?;
+                steps.insert(0, step);
+                write_todo(&steps, config)?;
+                return Err(e);
+            }
+        };
+        match outcome {
+            Ok(tree_hash) => {
+                commit_step(&step, tree_hash, config)?;
+                clear_step(config)?;
+            }
+            Err(conflicts) => {
+                let list = conflicts
+                    .iter()
+                    .map(|path| format!("{}\n", path.display()))
+                    .collect::<String>();
+                fs::write(state_dir.join(CONFLICTS), list)?;
+                return Ok(RebaseOutcome::Stopped(conflicts));
+            }
+        }
+    }
+}
+
+/// Commits `tree_hash` for `step`: a new commit with the picked message
+/// (edited for `reword`), or, for `squash`, a replacement of `HEAD` whose
+/// message joins both. Picks that change nothing are left out.
+fn commit_step(step: &Step, tree_hash: Sha1Hash, config: &Config) -> Result<()> {
+    let picked = GitObject::read_commit(&config.obj_db_path, &step.commit)?;
+    let head = refs::read_ref(refs::HEAD, config)?
+        .ok_or_else(|| GitError::UnknownRevision(refs::HEAD.to_owned()))?;
+    let head_commit = GitObject::read_commit(&config.obj_db_path, &head)?;
+    if step.action != Action::Squash && head_commit.tree() == &tree_hash {
+        debug!("{} is already applied", step.commit);
+        return Ok(());
+    }
+
+    let author_info = picked.author_info().to_owned();
+    let _ = match step.action {
+        Action::Pick => commit_on_head(
+            tree_hash,
+            false,
+            author_info,
+            picked.user_comment().to_owned(),
+            "rebase (pick)",
+            config,
+        )?,
+        Action::Reword => {
+            let message = edit_message(picked.user_comment(), config)?;
+            if message.trim().is_empty() {
+                return Err(GitError::EmptyCommitMessage);
+            }
+            commit_on_head(
+                tree_hash,
+                false,
+                author_info,
+                format!("{}\n", message.trim_end()),
+                "rebase (reword)",
+                config,
+            )?
+        }
+        Action::Squash => {
+            let message = format!(
+                "{}\n\n{}",
+                head_commit.user_comment().trim_end(),
+                picked.user_comment()
+            );
+            commit_on_head(
+                tree_hash,
+                true,
+                head_commit.author_info().to_owned(),
+                message,
+                "rebase (squash)",
+                config,
+            )?
+        }
+        Action::Drop => unreachable!("dropped steps are not committed"),
+    };
+
+    Ok(())
+}
+
+/// Points the branch being rebased at the new `HEAD` and checks it out.
+fn finish(config: &Config) -> Result<RebaseOutcome> {
+    let head = refs::read_ref(refs::HEAD, config)?
+        .ok_or_else(|| GitError::UnknownRevision(refs::HEAD.to_owned()))?;
+    let head_name = read_state(HEAD_NAME, config)?.unwrap_or_default();
+    let onto = read_state(ONTO, config)?.unwrap_or_default();
+
+    if head_name != DETACHED {
+        refs::write_ref(
+            &head_name,
+            &head,
+            &format!("rebase (finish): {} onto {}", head_name, onto),
+            config,
+        )?;
+        refs::write_symref(refs::HEAD, &head_name, config)?;
+        reflog::append_reflog(
+            refs::HEAD,
+            &head,
+            &head,
+            &format!("rebase (finish): returning to {}", head_name),
+            config,
+        )?;
+    }
+
+    fs::remove_dir_all(config.repo_path.join(REBASE_DIR))?;
+    Ok(RebaseOutcome::Finished(head))
+}
+
+/// Lists the commits reachable from `head` along first parents that are not
+/// reachable from `upstream`, oldest first. Merge commits are left out.
+pub(crate) fn unique_commits(
+    head: &Sha1Hash,
+    upstream: &Sha1Hash,
+    config: &Config,
+) -> Result<Vec<Sha1Hash>> {
+    let upstream_commits = reachable_commits(slice::from_ref(upstream), config)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    let mut commits = Vec::new();
+    let mut next = Some(head.clone());
+    while let Some(sha1) = next.take() {
+        if upstream_commits.contains(&sha1) {
+            break;
+        }
+        let commit_obj = GitObject::read_commit(&config.obj_db_path, &sha1)?;
+        next = commit_obj.parents().first().cloned();
+        if commit_obj.parents().len() <= 1 {
+            commits.push(sha1);
+        }
+    }
+    commits.reverse();
+
+    Ok(commits)
+}
+
+/// Fails unless the cache and the tracked files match `tree_hash`.
+pub(crate) fn check_clean(tree_hash: &Sha1Hash, config: &Config) -> Result<()> {
+    let head_tree = GitObject::read_tree_ish(&config.obj_db_path, tree_hash)?;
+    let index_tree = GitObject::read_tree_ish(&config.obj_db_path, &write_tree(config)?)?;
+
+    let mut changed = head_tree
+        .entries
+        .keys()
+        .chain(index_tree.entries.keys())
+        .filter(|name| {
+            let old = head_tree
+                .entries
+                .get(*name)
+                .map(|e| (e.st_mode(), e.sha1()));
+            let new = index_tree
+                .entries
+                .get(*name)
+                .map(|e| (e.st_mode(), e.sha1()));
+            old != new
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    let filter = LsFilesFilter {
+        deleted: true,
+        modified: true,
+        ..LsFilesFilter::default()
+    };
+    changed.extend(
+        ls_files(filter, false, config)?
+            .into_iter()
+            .map(PathBuf::from),
+    );
+    changed.sort();
+    changed.dedup();
+
+    if changed.is_empty() {
+        Ok(())
+    } else {
+        Err(GitError::LocalChanges(changed))
+    }
+}
+
+/// Parses a todo list, skipping blank lines and `#` comments. Commits may be
+/// given as any revision.
+fn parse_todo(todo: &str, config: &Config) -> Result<Vec<Step>> {
+    todo.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some(action), Some(rev)) => Ok(Step {
+                    action: action.parse()?,
+                    commit: parse_revision(rev, config)?,
+                }),
+                _ => Err(GitError::ParseTodoError(line.to_owned())),
+            }
+        })
+        .collect()
+}
+
+fn write_todo(steps: &[Step], config: &Config) -> Result<()> {
+    let mut todo = String::new();
+    for step in steps {
+        let commit_obj = GitObject::read_commit(&config.obj_db_path, &step.commit)?;
+        let subject = commit_obj.user_comment().lines().next().unwrap_or_default();
+        todo.push_str(&format!("{} {} {}\n", step.action, step.commit, subject));
+    }
+
+    fs::write(config.repo_path.join(REBASE_DIR).join(TODO), todo)?;
+    Ok(())
+}
+
+fn check_in_progress(config: &Config) -> Result<()> {
+    if config.repo_path.join(REBASE_DIR).exists() {
+        Ok(())
+    } else {
+        Err(GitError::NoOperation("rebase".to_owned()))
+    }
+}
+
+fn read_state(name: &str, config: &Config) -> Result<Option<String>> {
+    match fs::read_to_string(config.repo_path.join(REBASE_DIR).join(name)) {
+        Ok(content) => Ok(Some(content.trim_end().to_owned())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn clear_step(config: &Config) -> Result<()> {
+    for name in [CURRENT, CONFLICTS] {
+        match fs::remove_file(config.repo_path.join(REBASE_DIR).join(name)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
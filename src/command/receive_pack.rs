@@ -0,0 +1,101 @@
+use std::io::{Read, Write};
+
+use tracing::{debug, error};
+
+use crate::{
+    pack::read_pack,
+    refs::{self, RefTransaction},
+    transport::{read_pkt_line, split_line, write_flush, write_pkt_line, write_ref_advertisement},
+    Config, GitError, GitObject, Result, Sha1Hash,
+};
+
+#[derive(Debug)]
+struct RefUpdate {
+    old: Sha1Hash,
+    new: Sha1Hash,
+    name: String,
+}
+
+pub fn receive_pack<R: Read, W: Write>(mut input: R, mut output: W, config: &Config) -> Result<()> {
+    write_ref_advertisement(&mut output, config)?;
+
+    let mut updates = Vec::new();
+    while let Some(line) = read_pkt_line(&mut input)? {
+        let (old, rest) = split_line(&line)?;
+        let (new, name) = split_line(rest)?;
+        updates.push(RefUpdate {
+            old: old.parse()?,
+            new: new.parse()?,
+            name: name.to_owned(),
+        });
+    }
+    debug!(?updates);
+    if updates.is_empty() {
+        return Ok(());
+    }
+
+    if updates.iter().any(|update| !update.new.is_null()) {
+        match read_pack(&mut input, config) {
+            Ok(_) => write_pkt_line(&mut output, "unpack ok")?,
+            Err(e) => {
+                error!("unpack failed: {}", e);
+                write_pkt_line(&mut output, &format!("unpack {}", e))?;
+                write_flush(&mut output)?;
+                return Err(e);
+            }
+        }
+    } else {
+        write_pkt_line(&mut output, "unpack ok")?;
+    }
+
+    // Moving the checked out branch, directly or through `HEAD`, would leave
+    // the work tree and cache behind it.
+    let checked_out = refs::resolve_ref(refs::HEAD, config)?;
+    let mut refused = Vec::new();
+    for update in &updates {
+        refused.push(
+            refs::check_ref_name(&update.name).is_ok()
+                && refs::resolve_ref(&update.name, config)? == checked_out,
+        );
+    }
+    let accepted = updates
+        .iter()
+        .zip(&refused)
+        .filter(|(_, refused)| !**refused)
+        .map(|(update, _)| update)
+        .collect::<Vec<_>>();
+
+    let mut transaction = RefTransaction::new("push");
+    for update in &accepted {
+        transaction.update(&update.name, update.old.clone(), update.new.clone());
+    }
+
+    let result = check_updates(&accepted, config).and_then(|_| transaction.commit(config));
+    for (update, refused) in updates.iter().zip(refused) {
+        if refused {
+            error!("refusing to update checked out branch {}", update.name);
+            write_pkt_line(&mut output, &format!("ng {} checked out", update.name))?;
+            continue;
+        }
+        match &result {
+            Ok(()) => write_pkt_line(&mut output, &format!("ok {}", update.name))?,
+            Err(e) => {
+                error!("failed to update {}: {}", update.name, e);
+                write_pkt_line(&mut output, &format!("ng {} {}", update.name, e))?;
+            }
+        }
+    }
+
+    write_flush(&mut output)
+}
+
+fn check_updates(updates: &[&RefUpdate], config: &Config) -> Result<()> {
+    for update in updates {
+        refs::check_ref_name(&update.name)?;
+        if !update.new.is_null() && !GitObject::has_sha1_file(&config.obj_db_path, &update.new) {
+            return Err(GitError::MissingObject(update.new.clone()));
+        }
+    }
+
+    Ok(())
+}
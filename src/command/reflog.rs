@@ -0,0 +1,46 @@
+use chrono::Duration;
+
+use crate::{
+    reflog::{list_reflogs, read_reflog, retain_reflog, ReflogEntry},
+    refs,
+    revision::resolve_ref_name,
+    Config, GitError, Result,
+};
+
+/// Returns the reflog of `name` (default `HEAD`), newest entry first.
+pub fn reflog_show(name: Option<&str>, config: &Config) -> Result<(String, Vec<ReflogEntry>)> {
+    let name = match name {
+        Some(name) => resolve_ref_name(name, config)?
+            .ok_or_else(|| GitError::UnknownRevision(name.to_owned()))?,
+        None => refs::HEAD.to_owned(),
+    };
+
+    let mut entries = read_reflog(&name, config)?;
+    entries.reverse();
+
+    Ok((name, entries))
+}
+
+/// Drops reflog entries older than `expire` from the named refs, or from
+/// every reflog when `names` is empty. Returns the number of pruned entries.
+pub fn reflog_expire(names: &[String], expire: Duration, config: &Config) -> Result<usize> {
+    let names = if names.is_empty() {
+        list_reflogs(config)?
+    } else {
+        names
+            .iter()
+            .map(|name| {
+                resolve_ref_name(name, config)?
+                    .ok_or_else(|| GitError::UnknownRevision(name.clone()))
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let cutoff = (config.commit_date - expire).timestamp();
+    let mut expired = 0;
+    for name in names {
+        expired += retain_reflog(&name, |entry| entry.timestamp >= cutoff, config)?;
+    }
+
+    Ok(expired)
+}
@@ -0,0 +1,92 @@
+use std::{
+    collections::BTreeMap,
+    mem,
+    path::{Path, PathBuf},
+};
+
+use tracing::debug;
+
+use crate::{
+    ignore::normalize_path, refs, worktree, Cache, CacheLock, Config, GitObject, Result, Sha1Hash,
+    Tree,
+};
+
+/// How much of the repository `reset` moves to the target commit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetMode {
+    /// Only the current branch.
+    Soft,
+    /// The current branch and the cache.
+    Mixed,
+    /// The current branch, the cache and the work tree.
+    Hard,
+}
+
+/// Moves the current branch, or `HEAD` when detached, to `commit_hash`, and
+/// the cache and work tree along with it according to `mode`. `rev` is the
+/// revision as given, for the reflog.
+pub fn reset(commit_hash: &Sha1Hash, rev: &str, mode: ResetMode, config: &Config) -> Result<()> {
+    let commit_obj = GitObject::read_commit(&config.obj_db_path, commit_hash)?;
+    match mode {
+        ResetMode::Soft => {}
+        ResetMode::Mixed => {
+            let tree_obj = GitObject::read_tree_ish(&config.obj_db_path, commit_obj.tree())?;
+            reset_entries(&tree_obj, |_| true, config)?;
+        }
+        ResetMode::Hard => worktree::checkout_tree(commit_obj.tree(), config)?,
+    }
+
+    refs::write_ref(
+        refs::HEAD,
+        commit_hash,
+        &format!("reset: moving to {}", rev),
+        config,
+    )
+}
+
+/// Restores the cache entries of `paths`, or of the files below them, from
+/// `tree_ish`, leaving the work tree and `HEAD` untouched. Entries missing
+/// from the tree are removed from the cache.
+pub fn reset_paths(tree_ish: &Sha1Hash, paths: &[PathBuf], config: &Config) -> Result<()> {
+    let tree_obj = GitObject::read_tree_ish(&config.obj_db_path, tree_ish)?;
+    let paths = paths
+        .iter()
+        .map(|path| normalize_path(path))
+        .collect::<Vec<_>>();
+
+    reset_entries(
+        &tree_obj,
+        |name| paths.iter().any(|path| name.starts_with(path)),
+        config,
+    )
+}
+
+/// Makes the cache entries `selected` accepts match `tree_obj`. Entries
+/// whose hash and mode are unchanged keep their stat data.
+fn reset_entries<F>(tree_obj: &Tree, selected: F, config: &Config) -> Result<()>
+where
+    F: Fn(&Path) -> bool,
+{
+    let lock = CacheLock::acquire(&config.cache_path)?;
+    let mut cache = Cache::read_cache(&config.cache_path)?;
+
+    let (mut old_entries, kept): (BTreeMap<_, _>, _) = mem::take(&mut cache.entries)
+        .into_iter()
+        .partition(|(name, _)| selected(name));
+    cache.entries = kept;
+    for (name, entry) in tree_obj.entries.iter().filter(|(name, _)| selected(name)) {
+        match old_entries.remove(name) {
+            Some(old_entry)
+                if &old_entry.sha1 == entry.sha1() && old_entry.st_mode == entry.st_mode() =>
+            {
+                let _ = cache.entries.insert(name.clone(), old_entry);
+            }
+            _ => {
+                debug!("resetting {:?} to {}", name, entry.sha1());
+                cache.insert_unstated(name.clone(), entry.st_mode(), entry.sha1().clone());
+            }
+        }
+    }
+
+    lock.commit(&cache)
+}
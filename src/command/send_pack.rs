@@ -0,0 +1,76 @@
+use std::io::{Read, Write};
+
+use tracing::debug;
+
+use crate::{
+    pack::write_pack,
+    refs::{self, RefSpec},
+    transport::{read_pkt_line, read_ref_advertisement, write_flush, write_pkt_line},
+    walk::{is_fast_forward, reachable_objects},
+    Config, GitError, Result, Sha1Hash,
+};
+
+/// Updates the remote refs named by `specs` and sends their missing objects,
+/// returning the status report lines. Non-fast-forward updates are rejected
+/// locally unless the refspec is forced.
+pub fn send_pack<R: Read, W: Write>(
+    specs: &[RefSpec],
+    mut input: R,
+    mut output: W,
+    config: &Config,
+) -> Result<Vec<String>> {
+    let remote_refs = read_ref_advertisement(&mut input)?;
+
+    let mut report = Vec::new();
+    let mut commands = 0;
+    let mut tips = Vec::new();
+    for spec in specs {
+        let new = match &spec.src {
+            Some(src) => {
+                refs::read_ref(src, config)?.ok_or_else(|| GitError::InvalidRefName(src.clone()))?
+            }
+            None => Sha1Hash::default(),
+        };
+        let old = remote_refs
+            .iter()
+            .find(|(remote_name, _)| remote_name == &spec.dst)
+            .map(|(_, sha1)| sha1.clone())
+            .unwrap_or_default();
+
+        if old == new {
+            debug!("{} is up to date", spec.dst);
+            continue;
+        }
+        if !spec.force && !old.is_null() && !new.is_null() && !is_fast_forward(&old, &new, config)?
+        {
+            report.push(format!("ng {} non-fast-forward", spec.dst));
+            continue;
+        }
+
+        write_pkt_line(&mut output, &format!("{} {} {}", old, new, spec.dst))?;
+        commands += 1;
+        if !new.is_null() {
+            tips.push(new);
+        }
+    }
+    write_flush(&mut output)?;
+    if commands == 0 {
+        return Ok(report);
+    }
+
+    if !tips.is_empty() {
+        let remote_tips = remote_refs
+            .into_iter()
+            .map(|(_, sha1)| sha1)
+            .collect::<Vec<_>>();
+        let objects = reachable_objects(&tips, &remote_tips, config)?;
+        debug!("sending {} objects", objects.len());
+        write_pack(&objects, &mut output, config)?;
+    }
+
+    while let Some(line) = read_pkt_line(&mut input)? {
+        report.push(line);
+    }
+
+    Ok(report)
+}
@@ -1,17 +1,35 @@
 use std::{
-    io::Write,
+    fs::{self, File},
+    io::{self, ErrorKind, Read, Write},
+    os::unix::ffi::OsStringExt,
     process::{Command, Stdio},
 };
 
-use crate::{Cache, Config, GitObject, Result};
+use crate::{
+    attributes::Attributes,
+    diff::{is_binary_diff, FileStat, BINARY_CHECK_SIZE},
+    merge::read_blob,
+    worktree, Cache, Config, GitObject, Result, GITLINK_MODE, SYMLINK_MODE,
+};
 
 pub fn show_diff(config: &Config) -> Result<()> {
     let cache = Cache::read_cache(&config.cache_path)?;
+    let attributes = Attributes::from_config(config)?;
     for (_, entry) in cache.entries {
         let src_path = &entry.name;
         print!("{:?}: ", &src_path);
 
-        let curr_stat = src_path.metadata()?;
+        if entry.st_mode == GITLINK_MODE {
+            match worktree::gitlink_head(src_path)? {
+                Some(head) if head == entry.sha1 => println!("ok"),
+                Some(head) => println!("{} -> {}", entry.sha1, head),
+                None => println!("{} (not initialized)", entry.sha1),
+            }
+            continue;
+        }
+
+        let curr_stat = src_path.symlink_metadata()?;
+        let is_symlink = curr_stat.file_type().is_symlink();
         if entry.match_stat(curr_stat) == 0 {
             println!("ok");
             continue;
@@ -19,13 +37,34 @@ pub fn show_diff(config: &Config) -> Result<()> {
 
         println!("{}", &entry.sha1);
 
-        let obj = GitObject::read_sha1_file(&config.obj_db_path, &entry.sha1)?;
-        let blob_obj = match obj {
-            GitObject::Blob(blob) => blob,
-            GitObject::Tree(_) | GitObject::Commit(_) => {
-                unreachable!("expect a blob object");
+        let mut blob_reader = GitObject::open_blob(&config.obj_db_path, &entry.sha1)?;
+
+        if entry.st_mode == SYMLINK_MODE {
+            let mut old_target = Vec::new();
+            let _ = blob_reader.read_to_end(&mut old_target)?;
+            println!("-> {}", String::from_utf8_lossy(&old_target));
+            if is_symlink {
+                println!("-> {}", fs::read_link(src_path)?.display());
             }
-        };
+            continue;
+        }
+
+        // Only the start of each version is needed to tell binary files.
+        let mut old_start = Vec::new();
+        let _ = blob_reader
+            .by_ref()
+            .take(BINARY_CHECK_SIZE as u64)
+            .read_to_end(&mut old_start)?;
+        if !is_symlink {
+            let mut new_start = Vec::new();
+            let _ = File::open(src_path)?
+                .take(BINARY_CHECK_SIZE as u64)
+                .read_to_end(&mut new_start)?;
+            if is_binary_diff(src_path, &old_start, &new_start, &attributes) {
+                println!("Binary files differ");
+                continue;
+            }
+        }
 
         let mut diff = Command::new("diff")
             .arg("--strip-trailing-cr")
@@ -35,12 +74,61 @@ pub fn show_diff(config: &Config) -> Result<()> {
             .stdin(Stdio::piped())
             .spawn()?;
 
-        diff.stdin
+        let mut diff_stdin = diff
+            .stdin
             .take()
-            .expect("Child process stdin has not been captured!")
-            .write_all(blob_obj.as_ref())?;
+            .expect("Child process stdin has not been captured!");
+        diff_stdin.write_all(&old_start)?;
+        let _ = io::copy(&mut blob_reader, &mut diff_stdin)?;
+        drop(diff_stdin);
         let _ = diff.wait()?;
     }
 
     Ok(())
 }
+
+/// Counts the changes of each file in the working directory that differs
+/// from its cache entry. A deleted file counts as emptied.
+pub fn show_diff_stats(config: &Config) -> Result<Vec<FileStat>> {
+    let cache = Cache::read_cache(&config.cache_path)?;
+    let attributes = Attributes::from_config(config)?;
+
+    let mut stats = Vec::new();
+    for (_, entry) in cache.entries {
+        let src_path = &entry.name;
+        let (old, new) = if entry.st_mode == GITLINK_MODE {
+            match worktree::gitlink_head(src_path)? {
+                Some(head) if head != entry.sha1 => (
+                    format!("Subproject commit {}\n", entry.sha1).into_bytes(),
+                    format!("Subproject commit {}\n", head).into_bytes(),
+                ),
+                _ => continue,
+            }
+        } else {
+            let new = match src_path.symlink_metadata() {
+                Ok(stat) if entry.match_stat(stat.clone()) == 0 => continue,
+                Ok(stat) if stat.file_type().is_symlink() => {
+                    fs::read_link(src_path)?.into_os_string().into_vec()
+                }
+                Ok(_) => fs::read(src_path)?,
+                Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e.into()),
+            };
+            let old = read_blob(&entry.sha1, config)?;
+            if old == new {
+                continue;
+            }
+            (old, new)
+        };
+
+        let binary = is_binary_diff(src_path, &old, &new, &attributes);
+        stats.push(FileStat::new(
+            src_path.display().to_string(),
+            &old,
+            &new,
+            binary,
+        ));
+    }
+
+    Ok(stats)
+}
@@ -0,0 +1,232 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use tracing::{debug, error};
+
+use super::write_tree;
+use crate::{
+    cache::cache_mode,
+    diff::diff_trees,
+    merge::{merge_trees, MergeOutcome},
+    reflog::{self, ReflogEntry},
+    refs, worktree, Cache, CacheLock, Commit, Config, GitError, GitObject, Result, Sha1Hash, Tree,
+    TreeEntry, GITLINK_MODE,
+};
+
+const STASH_REF: &str = "refs/stash";
+
+/// Saves the cache and the tracked files of the work tree as a commit on
+/// `refs/stash`, then resets both to `HEAD`. The stash commit has `HEAD`
+/// and a commit of the cache as parents.
+pub fn stash_push(message: Option<&str>, config: &Config) -> Result<Sha1Hash> {
+    let head = refs::read_ref(refs::HEAD, config)?
+        .ok_or_else(|| GitError::UnknownRevision(refs::HEAD.to_owned()))?;
+    let head_commit = GitObject::read_commit(&config.obj_db_path, &head)?;
+
+    let index_tree = write_tree(config)?;
+    let work_tree = write_work_tree(config)?;
+    if &index_tree == head_commit.tree() && &work_tree == head_commit.tree() {
+        return Err(GitError::NoLocalChanges);
+    }
+
+    let branch = match refs::read_symref(refs::HEAD, config)? {
+        Some(target) => target
+            .strip_prefix(refs::HEADS_PREFIX)
+            .unwrap_or(&target)
+            .to_owned(),
+        None => "(no branch)".to_owned(),
+    };
+    let subject = head_commit
+        .user_comment()
+        .lines()
+        .next()
+        .unwrap_or_default();
+    let summary = format!("{}: {} {}", branch, &head.to_string()[..7], subject);
+
+    let index_commit = write_commit(
+        index_tree,
+        vec![head.clone()],
+        format!("index on {}\n", summary),
+        config,
+    )?;
+    let message = match message {
+        Some(message) => format!("On {}: {}", branch, message),
+        None => format!("WIP on {}", summary),
+    };
+    let stash_commit = write_commit(
+        work_tree,
+        vec![head, index_commit],
+        format!("{}\n", message),
+        config,
+    )?;
+
+    refs::write_ref(STASH_REF, &stash_commit, &message, config)?;
+    worktree::checkout_tree(head_commit.tree(), config)?;
+
+    Ok(stash_commit)
+}
+
+/// Lists the stashes, newest first.
+pub fn stash_list(config: &Config) -> Result<Vec<ReflogEntry>> {
+    let mut entries = reflog::read_reflog(STASH_REF, config)?;
+    entries.reverse();
+
+    Ok(entries)
+}
+
+/// Lists the paths stash `n` changed relative to the commit it was made on,
+/// each with `A`, `M` or `D`.
+pub fn stash_show(n: usize, config: &Config) -> Result<Vec<(char, PathBuf)>> {
+    let (_, stash_commit) = stash_entry(n, config)?;
+    let base = GitObject::read_tree_ish(&config.obj_db_path, &stash_commit.parents()[0])?;
+    let stashed = GitObject::read_tree_ish(&config.obj_db_path, stash_commit.tree())?;
+
+    Ok(diff_trees(&base, &stashed)
+        .iter()
+        .map(|change| (change.status(), change.path().to_owned()))
+        .collect())
+}
+
+/// Merges the changes of stash `n` into the work tree with a three-way merge
+/// against the commit it was made on, and its cache commit into the cache the
+/// same way, so staged changes come back staged. When the cache does not
+/// merge cleanly, only new files are staged. Returns the conflicting paths,
+/// which are written with conflict markers and left unstaged.
+pub fn stash_apply(n: usize, config: &Config) -> Result<Vec<PathBuf>> {
+    let (_, stash_commit) = stash_entry(n, config)?;
+    let base = GitObject::read_tree_ish(&config.obj_db_path, &stash_commit.parents()[0])?;
+    let stashed = GitObject::read_tree_ish(&config.obj_db_path, stash_commit.tree())?;
+    let stashed_index = GitObject::read_tree_ish(&config.obj_db_path, &stash_commit.parents()[1])?;
+
+    let head = refs::read_ref(refs::HEAD, config)?
+        .ok_or_else(|| GitError::UnknownRevision(refs::HEAD.to_owned()))?;
+    let ours = GitObject::read_tree_ish(&config.obj_db_path, &head)?;
+
+    let labels = ("Updated upstream", "Stashed changes");
+    let outcome = merge_trees(&base, &ours, &stashed, labels, config)?;
+    let index_outcome = merge_trees(&base, &ours, &stashed_index, labels, config)?;
+    let unstaged = outcome
+        .tree
+        .entries
+        .keys()
+        .filter(|name| ours.entries.contains_key(*name) || outcome.conflicts.contains(name))
+        .cloned()
+        .collect::<Vec<_>>();
+    worktree::switch_tree(&ours, &outcome.tree, &unstaged, config)?;
+    if index_outcome.conflicts.is_empty() {
+        restore_index(&ours, &index_outcome.tree, &outcome, config)?;
+    } else {
+        error!("the cache of stash@{{{}}} conflicts, not restoring it", n);
+    }
+    debug!(
+        "applied stash@{{{}}} with {} conflicts",
+        n,
+        outcome.conflicts.len()
+    );
+
+    Ok(outcome.conflicts)
+}
+
+/// Stages the paths `index` changes from `ours`, leaving out the conflicts of
+/// the work tree merge `work`. Paths whose work tree content differs from
+/// `index` are staged without stat data so they read as changed.
+fn restore_index(ours: &Tree, index: &Tree, work: &MergeOutcome, config: &Config) -> Result<()> {
+    let lock = CacheLock::acquire(&config.cache_path)?;
+    let mut cache = Cache::read_cache(&config.cache_path)?;
+
+    for change in diff_trees(ours, index) {
+        let name = change.path().to_owned();
+        if work.conflicts.contains(&name) {
+            continue;
+        }
+        match index.entries.get(&name) {
+            Some(entry) => {
+                let in_work_tree = work.tree.entries.get(&name).is_some_and(|work_entry| {
+                    work_entry.sha1() == entry.sha1() && work_entry.st_mode() == entry.st_mode()
+                });
+                if in_work_tree {
+                    cache.insert_in(&config.work_dir, name, entry.sha1().clone())?;
+                } else {
+                    cache.insert_unstated(name, entry.st_mode(), entry.sha1().clone());
+                }
+            }
+            None => {
+                let _ = cache.entries.remove(&name);
+            }
+        }
+    }
+
+    lock.commit(&cache)
+}
+
+/// Removes stash `n`, pointing `refs/stash` at the next newest one.
+pub fn stash_drop(n: usize, config: &Config) -> Result<Sha1Hash> {
+    let (entry, _) = stash_entry(n, config)?;
+    let total = reflog::read_reflog(STASH_REF, config)?.len();
+
+    let drop_index = total - 1 - n;
+    let mut index = 0;
+    let _ = reflog::retain_reflog(
+        STASH_REF,
+        |_| {
+            index += 1;
+            index - 1 != drop_index
+        },
+        config,
+    )?;
+
+    match reflog::read_reflog(STASH_REF, config)?.last() {
+        Some(newest) => refs::set_ref(STASH_REF, &newest.new, config)?,
+        None => refs::delete_ref(STASH_REF, config)?,
+    }
+
+    Ok(entry.new)
+}
+
+fn stash_entry(n: usize, config: &Config) -> Result<(ReflogEntry, Commit)> {
+    let entry = stash_list(config)?
+        .into_iter()
+        .nth(n)
+        .ok_or_else(|| GitError::UnknownRevision(format!("stash@{{{}}}", n)))?;
+    let stash_commit = GitObject::read_commit(&config.obj_db_path, &entry.new)?;
+
+    Ok((entry, stash_commit))
+}
+
+fn write_commit(
+    tree: Sha1Hash,
+    parents: Vec<Sha1Hash>,
+    message: String,
+    config: &Config,
+) -> Result<Sha1Hash> {
+    let commit_obj = GitObject::new_commit(tree, parents, message, config);
+    GitObject::from(commit_obj).write_sha1_file(config)
+}
+
+/// Writes a tree of the current content of every tracked file, leaving out
+/// deleted ones.
+fn write_work_tree(config: &Config) -> Result<Sha1Hash> {
+    let cache = Cache::read_cache(&config.cache_path)?;
+    let mut tree_obj = Tree::default();
+    for (name, entry) in cache.entries {
+        let path = config.work_dir.join(&name);
+        let meta = match path.symlink_metadata() {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let st_mode = cache_mode(&meta);
+        let sha1 = if entry.st_mode == GITLINK_MODE {
+            worktree::gitlink_head(&path)?.unwrap_or(entry.sha1)
+        } else if entry.match_stat(meta) == 0 {
+            entry.sha1
+        } else {
+            GitObject::write_blob_file(&path, config)?
+        };
+        let _ = tree_obj
+            .entries
+            .insert(name.clone(), TreeEntry::new(st_mode, name, sha1));
+    }
+
+    GitObject::from(tree_obj).write_sha1_file(config)
+}
@@ -0,0 +1,113 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use tracing::{debug, error};
+
+use crate::{
+    config::REPO_DIRECTORY, fetch_pack, init_db, refs, transport::Connection, worktree, Cache,
+    Config, GitError, GitObject, Result, Sha1Hash, GITLINK_MODE,
+};
+
+const MODULES_FILE: &str = ".dircachemodules";
+
+/// A line of `.dircachemodules`: the path of a nested repository in the work
+/// tree and the repository it is fetched from, relative to the work tree.
+#[derive(Debug)]
+struct Submodule {
+    path: PathBuf,
+    url: PathBuf,
+}
+
+fn read_manifest(config: &Config) -> Result<Vec<Submodule>> {
+    let content = match fs::read_to_string(config.work_dir.join(MODULES_FILE)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(
+            |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [path, _] if !worktree::is_valid_path(Path::new(path)) => {
+                    Err(GitError::InvalidPath(PathBuf::from(path)))
+                }
+                [path, url] => Ok(Submodule {
+                    path: PathBuf::from(path),
+                    url: PathBuf::from(url),
+                }),
+                _ => Err(GitError::ParseManifestError(line.to_owned())),
+            },
+        )
+        .collect()
+}
+
+/// Creates an empty repository for every submodule in the manifest that is
+/// recorded as a gitlink in the cache and does not have one yet, returning
+/// their paths.
+pub fn submodule_init(config: &Config) -> Result<Vec<PathBuf>> {
+    let cache = Cache::read_cache(&config.cache_path)?;
+
+    let mut initialized = Vec::new();
+    for submodule in read_manifest(config)? {
+        match cache.entries.get(&submodule.path) {
+            Some(entry) if entry.st_mode == GITLINK_MODE => {}
+            _ => {
+                error!("{:?} is not recorded as a gitlink", submodule.path);
+                continue;
+            }
+        }
+        let work_dir = config.work_dir.join(&submodule.path);
+        if work_dir.join(REPO_DIRECTORY).is_dir() {
+            continue;
+        }
+
+        fs::create_dir_all(&work_dir)?;
+        init_db(&Config::with_work_dir(&work_dir)?)?;
+        initialized.push(submodule.path);
+    }
+
+    Ok(initialized)
+}
+
+/// Checks out the commit recorded by each submodule's gitlink, fetching it
+/// with `upload_pack` first when the nested repository lacks it. Returns the
+/// updated paths and commits.
+pub fn submodule_update(upload_pack: &Path, config: &Config) -> Result<Vec<(PathBuf, Sha1Hash)>> {
+    let _ = submodule_init(config)?;
+    let cache = Cache::read_cache(&config.cache_path)?;
+
+    let mut updated = Vec::new();
+    for submodule in read_manifest(config)? {
+        let commit_hash = match cache.entries.get(&submodule.path) {
+            Some(entry) if entry.st_mode == GITLINK_MODE => entry.sha1.clone(),
+            _ => {
+                error!("{:?} is not recorded as a gitlink", submodule.path);
+                continue;
+            }
+        };
+
+        let sub_config = Config::with_work_dir(&config.work_dir.join(&submodule.path))?;
+        if !GitObject::has_sha1_file(&sub_config.obj_db_path, &commit_hash) {
+            let remote = config.work_dir.join(&submodule.url);
+            debug!("fetching {:?} from {:?}", submodule.path, remote);
+
+            let mut conn = Connection::spawn(upload_pack, "upload-pack", &remote)?;
+            let _ = fetch_pack(&[], &mut conn.reader, &mut conn.writer, &sub_config)?;
+            conn.finish()?;
+        }
+
+        let commit_obj = GitObject::read_commit(&sub_config.obj_db_path, &commit_hash)?;
+        worktree::checkout_tree(commit_obj.tree(), &sub_config)?;
+        refs::detach_head(&commit_hash, "submodule update", &sub_config)?;
+
+        updated.push((submodule.path, commit_hash));
+    }
+
+    Ok(updated)
+}
@@ -0,0 +1,88 @@
+use tracing::debug;
+
+use crate::{
+    reflog, refs, revision::parse_revision, worktree, Config, GitError, GitObject, Result, Tree,
+};
+
+/// Switches the work tree, cache and `HEAD` to `rev`. With `new_branch`, a
+/// branch of that name is created at `rev` and checked out. Otherwise `rev`
+/// is checked out as a branch if it names one, and as a detached `HEAD` if
+/// it is any other revision or `detach` is set. Fails without changing
+/// anything when local changes would be overwritten.
+pub fn switch(rev: &str, new_branch: Option<&str>, detach: bool, config: &Config) -> Result<()> {
+    let branch = match new_branch {
+        Some(name) => {
+            let branch = refs::expand_ref_name(name);
+            refs::check_ref_name(&branch)?;
+            if !branch.starts_with(refs::HEADS_PREFIX) {
+                return Err(GitError::InvalidRefName(name.to_owned()));
+            }
+            if refs::read_ref(&branch, config)?.is_some() {
+                return Err(GitError::RefExists(branch));
+            }
+            Some(branch)
+        }
+        None if detach => None,
+        None => {
+            let branch = refs::expand_ref_name(rev);
+            let is_branch = branch.starts_with(refs::HEADS_PREFIX)
+                && refs::check_ref_name(&branch).is_ok()
+                && refs::read_ref(&branch, config)?.is_some();
+            if is_branch {
+                Some(branch)
+            } else {
+                None
+            }
+        }
+    };
+
+    let commit_hash = parse_revision(rev, config)?;
+    let target = GitObject::read_commit(&config.obj_db_path, &commit_hash)?;
+    let head = refs::read_ref(refs::HEAD, config)?;
+    let current = match &head {
+        Some(head) => {
+            let head_commit = GitObject::read_commit(&config.obj_db_path, head)?;
+            GitObject::read_tree_ish(&config.obj_db_path, head_commit.tree())?
+        }
+        None => Tree::default(),
+    };
+    let to = GitObject::read_tree_ish(&config.obj_db_path, target.tree())?;
+    worktree::switch_tree(&current, &to, &[], config)?;
+
+    let from_name = match refs::read_symref(refs::HEAD, config)? {
+        Some(target) if head.is_some() => short_name(&target).to_owned(),
+        _ => head.clone().unwrap_or_default().to_string(),
+    };
+    let to_name = match &branch {
+        Some(branch) => short_name(branch).to_owned(),
+        None => commit_hash.to_string(),
+    };
+    let message = format!("checkout: moving from {} to {}", from_name, to_name);
+    debug!("{}", message);
+
+    match branch {
+        Some(branch) => {
+            if new_branch.is_some() {
+                refs::write_ref(
+                    &branch,
+                    &commit_hash,
+                    &format!("branch: Created from {}", rev),
+                    config,
+                )?;
+            }
+            refs::write_symref(refs::HEAD, &branch, config)?;
+            reflog::append_reflog(
+                refs::HEAD,
+                &head.unwrap_or_default(),
+                &commit_hash,
+                &message,
+                config,
+            )
+        }
+        None => refs::detach_head(&commit_hash, &message, config),
+    }
+}
+
+fn short_name(name: &str) -> &str {
+    name.strip_prefix(refs::HEADS_PREFIX).unwrap_or(name)
+}
@@ -1,32 +1,113 @@
-use std::{fs, path::PathBuf};
+use std::{
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{mpsc, Mutex},
+    thread,
+};
 
 use tracing::{debug, error};
 
-use crate::{Cache, Config, GitObject, Result};
+use crate::{
+    ignore::IgnoreRules, worktree, Cache, CacheLock, Config, GitObject, Result, GITLINK_MODE,
+};
 
-pub fn update_cache(paths: Vec<PathBuf>, config: &Config) -> Result<()> {
+/// Replaces directories in `paths` with the files below them, honoring the
+/// `.dircacheignore` rules and skipping the repository directory.
+pub fn expand_paths(paths: Vec<PathBuf>, config: &Config) -> Result<Vec<PathBuf>> {
+    IgnoreRules::from_config(config)?.expand_paths(paths)
+}
+
+/// Hashes and stores `paths` on `jobs` worker threads. A directory holding a
+/// nested repository is recorded as a gitlink to its `HEAD` commit. The
+/// calling thread is the only writer of the index, and entries are keyed by
+/// path so the resulting index does not depend on the order in which workers
+/// finish.
+pub fn update_cache(
+    paths: Vec<PathBuf>,
+    jobs: usize,
+    progress: bool,
+    config: &Config,
+) -> Result<()> {
     let mut cache = Cache::read_cache(&config.cache_path)?;
-    let lock_path = config.cache_path.with_file_name("index.lock");
-    let lock_file = fs::OpenOptions::new()
-        .create_new(true)
-        .write(true)
-        .open(&lock_path)?;
-
-    for src_path in paths {
-        let sha1 = match GitObject::new_blob(&src_path) {
-            Ok(blob_obj) => GitObject::from(blob_obj).write_sha1_file(&config.obj_db_path)?,
-            Err(e) => {
-                error!("Ignoring path {:?} cause: {}", &src_path, e);
-                continue;
+    let lock = CacheLock::acquire(&config.cache_path)?;
+
+    let total = paths.len();
+    let queue = Mutex::new(paths.into_iter());
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|s| -> Result<()> {
+        for _ in 0..jobs.max(1) {
+            let tx = tx.clone();
+            let queue = &queue;
+            let _ = s.spawn(move || loop {
+                let src_path = match queue.lock().expect("work queue poisoned").next() {
+                    Some(src_path) => src_path,
+                    None => break,
+                };
+
+                let result = match worktree::gitlink_head(&src_path) {
+                    Ok(Some(commit)) => Ok(commit),
+                    Ok(None) => GitObject::write_blob_file(&src_path, config),
+                    Err(e) => Err(e),
+                };
+                if tx.send((src_path, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        let mut last_percent = None;
+        for (done, (src_path, result)) in rx.iter().enumerate() {
+            match result {
+                Ok(sha1) => {
+                    debug!("src_path = {:?}, sha1 = {}", src_path, sha1);
+                    cache.insert(src_path, sha1)?;
+                }
+                Err(e) => error!("Ignoring path {:?} cause: {}", &src_path, e),
+            }
+
+            let percent = (done + 1) * 100 / total;
+            if progress && last_percent != Some(percent) {
+                eprint!("\rUpdating cache: {}% ({}/{})", percent, done + 1, total);
+                last_percent = Some(percent);
             }
-        };
-        debug!("src_path = {:?}, sha1 = {}", src_path, sha1,);
+        }
+        if progress && total > 0 {
+            eprintln!(", done.");
+        }
 
-        cache.insert(src_path, sha1)?;
+        Ok(())
+    })?;
+
+    lock.commit(&cache)
+}
+
+/// Re-stages tracked files whose stat data changed and drops the entries of
+/// files removed from the work tree.
+pub fn update_tracked(jobs: usize, config: &Config) -> Result<()> {
+    let lock = CacheLock::acquire(&config.cache_path)?;
+    let mut cache = Cache::read_cache(&config.cache_path)?;
+
+    let mut changed = Vec::new();
+    let mut removed = Vec::new();
+    for (name, entry) in &cache.entries {
+        match config.work_dir.join(name).symlink_metadata() {
+            Ok(meta) => {
+                if entry.st_mode == GITLINK_MODE || entry.match_stat(meta) != 0 {
+                    changed.push(name.clone());
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => removed.push(name.clone()),
+            Err(e) => return Err(e.into()),
+        }
     }
 
-    cache.write_cache(&lock_file)?;
-    fs::rename(&lock_path, &config.cache_path)?;
+    for name in removed {
+        debug!("removing {:?} from the cache", name);
+        let _ = cache.entries.remove(&name);
+    }
+    lock.commit(&cache)?;
 
-    Ok(())
+    update_cache(changed, jobs, false, config)
 }
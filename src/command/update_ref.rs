@@ -0,0 +1,10 @@
+use crate::{refs, revision::parse_revision, Config, GitError, GitObject, Result};
+
+pub fn update_ref(name: &str, rev: &str, message: &str, config: &Config) -> Result<()> {
+    let sha1 = parse_revision(rev, config)?;
+    if !GitObject::has_sha1_file(&config.obj_db_path, &sha1) {
+        return Err(GitError::MissingObject(sha1));
+    }
+
+    refs::write_ref(name, &sha1, message, config)
+}
@@ -0,0 +1,51 @@
+use std::io::{Read, Write};
+
+use tracing::debug;
+
+use crate::{
+    pack::write_pack,
+    transport::{
+        parse_hash_line, read_pkt_line, write_flush, write_pkt_line, write_ref_advertisement,
+    },
+    walk::reachable_objects,
+    Config, GitError, GitObject, Result,
+};
+
+pub fn upload_pack<R: Read, W: Write>(mut input: R, mut output: W, config: &Config) -> Result<()> {
+    write_ref_advertisement(&mut output, config)?;
+
+    let mut wants = Vec::new();
+    while let Some(line) = read_pkt_line(&mut input)? {
+        let want = parse_hash_line(&line, "want")?;
+        if !GitObject::has_sha1_file(&config.obj_db_path, &want) {
+            return Err(GitError::MissingObject(want));
+        }
+        wants.push(want);
+    }
+    if wants.is_empty() {
+        return Ok(());
+    }
+
+    let mut common = Vec::new();
+    loop {
+        let line = match read_pkt_line(&mut input)? {
+            Some(line) if line == "done" => break,
+            Some(line) => line,
+            None => continue,
+        };
+
+        let have = parse_hash_line(&line, "have")?;
+        if GitObject::has_sha1_file(&config.obj_db_path, &have) {
+            write_pkt_line(&mut output, &format!("ACK {}", have))?;
+            common.push(have);
+        }
+    }
+    if common.is_empty() {
+        write_pkt_line(&mut output, "NAK")?;
+    }
+    write_flush(&mut output)?;
+
+    let objects = reachable_objects(&wants, &common, config)?;
+    debug!("sending {} objects", objects.len());
+    write_pack(&objects, &mut output, config)
+}
@@ -10,7 +10,7 @@ pub fn write_tree(config: &Config) -> Result<Sha1Hash> {
         tree_obj.insert(blob_path, entry)?;
     }
 
-    let sha1 = GitObject::from(tree_obj).write_sha1_file(&config.obj_db_path)?;
+    let sha1 = GitObject::from(tree_obj).write_sha1_file(config)?;
     debug!("sha1 = {}", sha1);
 
     Ok(sha1)
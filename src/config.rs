@@ -1,21 +1,32 @@
-use std::{env, path::PathBuf};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
 
 use chrono::{DateTime, Utc};
 use whoami::{hostname, realname, username};
 
 use crate::Result;
 
+pub(crate) const REPO_DIRECTORY: &str = ".dircache";
 const DB_ENVIRONMENT: &str = "SHA1_FILE_DIRECTORY";
 const DEFAULT_DB_ENVIRONMENT: &str = "objects";
 
+const FSYNC_ENVIRONMENT: &str = "SHA1_FILE_FSYNC";
+
 const COMMITTER_NAME_ENV: &str = "COMMITTER_NAME";
 const COMMITTER_EMAIL_ENV: &str = "COMMITTER_EMAIL";
 
+const EDITOR_ENV: &str = "EDITOR";
+const DEFAULT_EDITOR: &str = "vi";
+
 #[derive(Debug)]
 pub struct Config {
+    pub work_dir: PathBuf,
     pub repo_path: PathBuf,
     pub obj_db_path: PathBuf,
     pub cache_path: PathBuf,
+    pub fsync_object_files: bool,
 
     pub author_name: String,
     pub author_email: String,
@@ -24,16 +35,25 @@ pub struct Config {
     pub committer_email: String,
 
     pub commit_date: DateTime<Utc>,
+
+    pub editor: String,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self> {
-        let repo_path = PathBuf::from(".dircache");
-        let obj_db_path = match env::var(DB_ENVIRONMENT) {
-            Ok(path) => PathBuf::from(path),
-            Err(_) => repo_path.join(DEFAULT_DB_ENVIRONMENT),
-        };
+        let mut config = Self::with_work_dir(Path::new(""))?;
+        if let Ok(path) = env::var(DB_ENVIRONMENT) {
+            config.obj_db_path = PathBuf::from(path);
+        }
+
+        Ok(config)
+    }
+
+    pub fn with_work_dir(work_dir: &Path) -> Result<Self> {
+        let repo_path = work_dir.join(REPO_DIRECTORY);
+        let obj_db_path = repo_path.join(DEFAULT_DB_ENVIRONMENT);
         let cache_path = repo_path.join("index");
+        let fsync_object_files = env::var_os(FSYNC_ENVIRONMENT).is_some();
 
         let author_name = realname();
         let author_email = format!("{}@{}", username(), hostname());
@@ -43,14 +63,17 @@ impl Config {
             env::var(COMMITTER_EMAIL_ENV).unwrap_or_else(|_| author_email.clone());
 
         Ok(Self {
+            work_dir: work_dir.to_owned(),
             repo_path,
             obj_db_path,
             cache_path,
+            fsync_object_files,
             author_name,
             author_email,
             committer_name,
             committer_email,
             commit_date: Utc::now(),
+            editor: env::var(EDITOR_ENV).unwrap_or_else(|_| DEFAULT_EDITOR.to_owned()),
         })
     }
 
@@ -0,0 +1,227 @@
+mod patch;
+mod stat;
+mod tree;
+
+use std::ops::Range;
+
+pub use patch::{
+    apply_hunks, parse_patch, tree_diff_patches, unified_hunks, FilePatch, Hunk, HunkLine,
+    DEFAULT_CONTEXT, MAX_FUZZ,
+};
+pub use stat::{
+    format_stats, is_binary, is_binary_diff, tree_diff_stats, FileStat, LineChanges, StatFormat,
+    BINARY_CHECK_SIZE,
+};
+pub use tree::{
+    detect_renames, diff_trees, similarity, RenameOptions, TreeChange, DEFAULT_RENAME_LIMIT,
+    DEFAULT_RENAME_THRESHOLD,
+};
+
+/// A run of the edit script turning `old` into `new`: lines both sequences
+/// share, or old lines replaced by new ones (either range may be empty).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiffOp {
+    Equal {
+        old: Range<usize>,
+        new: Range<usize>,
+    },
+    Change {
+        old: Range<usize>,
+        new: Range<usize>,
+    },
+}
+
+/// Computes a shortest edit script from `old` to `new` with Myers'
+/// algorithm, after trimming their common prefix and suffix.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<DiffOp> {
+    let mut ops = Vec::new();
+    let mut old_pos = 0;
+    let mut new_pos = 0;
+    for (old_index, new_index) in matching_lines(old, new) {
+        if old_index > old_pos || new_index > new_pos {
+            ops.push(DiffOp::Change {
+                old: old_pos..old_index,
+                new: new_pos..new_index,
+            });
+        }
+        match ops.last_mut() {
+            Some(DiffOp::Equal { old, new }) if old.end == old_index && new.end == new_index => {
+                old.end += 1;
+                new.end += 1;
+            }
+            _ => ops.push(DiffOp::Equal {
+                old: old_index..old_index + 1,
+                new: new_index..new_index + 1,
+            }),
+        }
+        old_pos = old_index + 1;
+        new_pos = new_index + 1;
+    }
+    if old_pos < old.len() || new_pos < new.len() {
+        ops.push(DiffOp::Change {
+            old: old_pos..old.len(),
+            new: new_pos..new.len(),
+        });
+    }
+
+    ops
+}
+
+/// Returns the pairs of indices of a longest common subsequence of `old`
+/// and `new`, in increasing order.
+pub fn matching_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut pairs = (0..prefix).map(|i| (i, i)).collect::<Vec<_>>();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+    pairs.extend(
+        myers(old_mid, new_mid)
+            .into_iter()
+            .map(|(i, j)| (i + prefix, j + prefix)),
+    );
+    pairs.extend((0..suffix).map(|i| (old.len() - suffix + i, new.len() - suffix + i)));
+
+    pairs
+}
+
+/// Myers' linear-space refinement: the middle snake of an optimal edit path
+/// splits the problem in two, so memory stays proportional to the input
+/// rather than to its product with the number of edits.
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    myers_between(a, b, 0, 0, &mut pairs);
+
+    pairs
+}
+
+/// Appends the matching pairs of `a` and `b`, which start at `a_start` and
+/// `b_start` in the whole sequences, to `pairs`.
+fn myers_between<T: PartialEq>(
+    a: &[T],
+    b: &[T],
+    a_start: usize,
+    b_start: usize,
+    pairs: &mut Vec<(usize, usize)>,
+) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    pairs.extend((0..prefix).map(|i| (a_start + i, b_start + i)));
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    if !a_mid.is_empty() && !b_mid.is_empty() {
+        if let Some((x, y)) = middle_snake(a_mid, b_mid) {
+            let (a_start, b_start) = (a_start + prefix, b_start + prefix);
+            myers_between(&a_mid[..x], &b_mid[..y], a_start, b_start, pairs);
+            myers_between(&a_mid[x..], &b_mid[y..], a_start + x, b_start + y, pairs);
+        }
+    }
+
+    let (a_end, b_end) = (
+        a_start + prefix + a_mid.len(),
+        b_start + prefix + b_mid.len(),
+    );
+    pairs.extend((0..suffix).map(|i| (a_end + i, b_end + i)));
+}
+
+/// Searches forward from the start and backward from the end of the edit
+/// graph at once until the paths meet, returning the point where the
+/// forward one ends. `None` means nothing matches.
+fn middle_snake<T: PartialEq>(a: &[T], b: &[T]) -> Option<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max_d = (n + m + 1) / 2;
+    let offset = max_d;
+    let mut forward = vec![-1_isize; 2 * max_d as usize + 2];
+    let mut backward = forward.clone();
+    forward[offset as usize + 1] = 0;
+    backward[offset as usize + 1] = 0;
+    let delta = n - m;
+    // With an odd delta the paths meet on a forward step, else a backward
+    // one.
+    let odd = delta % 2 != 0;
+    let (mut forward_start, mut forward_end) = (0, 0);
+    let (mut backward_start, mut backward_end) = (0, 0);
+
+    for d in 0..max_d {
+        for k in (-d + forward_start..=d - forward_end).step_by(2) {
+            let idx = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && forward[idx - 1] < forward[idx + 1]) {
+                forward[idx + 1]
+            } else {
+                forward[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[idx] = x;
+            if x > n {
+                forward_end += 2;
+            } else if y > m {
+                forward_start += 2;
+            } else if odd {
+                let reached = furthest(&backward, offset + delta - k);
+                if reached.is_some_and(|backward_x| x >= n - backward_x) {
+                    return Some((x as usize, y as usize));
+                }
+            }
+        }
+
+        for k in (-d + backward_start..=d - backward_end).step_by(2) {
+            let idx = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && backward[idx - 1] < backward[idx + 1]) {
+                backward[idx + 1]
+            } else {
+                backward[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[idx] = x;
+            if x > n {
+                backward_end += 2;
+            } else if y > m {
+                backward_start += 2;
+            } else if !odd {
+                let other = delta - k;
+                if let Some(forward_x) = furthest(&forward, offset + other) {
+                    if forward_x >= n - x {
+                        return Some((forward_x as usize, (forward_x - other) as usize));
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// The furthest `x` a search has reached on the diagonal at `idx`, if it
+/// has been there.
+fn furthest(reached: &[isize], idx: isize) -> Option<isize> {
+    if idx < 0 {
+        return None;
+    }
+
+    reached.get(idx as usize).copied().filter(|&x| x != -1)
+}
+
+/// Splits `content` into lines, each keeping its trailing newline.
+pub fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|&c| c == b'\n').collect()
+}
@@ -0,0 +1,619 @@
+use std::{
+    io::{self, Write},
+    mem,
+    path::{Path, PathBuf},
+};
+
+use tracing::debug;
+
+use super::{diff, is_binary_diff, split_lines, stat::entry_content, DiffOp, TreeChange};
+use crate::{attributes::Attributes, worktree, Config, GitError, Result, TreeEntry};
+
+/// How many unchanged lines surround the changes of a hunk.
+pub const DEFAULT_CONTEXT: usize = 3;
+/// How many context lines at either end of a hunk `apply_hunks` may ignore
+/// when the hunk does not match as it is, as `patch` does by default.
+pub const MAX_FUZZ: usize = 2;
+
+const NO_NEWLINE: &str = "\\ No newline at end of file";
+
+/// A line of a hunk, with its newline unless it is the last line of a file
+/// that lacks one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HunkLine {
+    Context(Vec<u8>),
+    Deleted(Vec<u8>),
+    Added(Vec<u8>),
+}
+
+/// A run of changed lines with their context. The starts are 1-based, or
+/// when a side has no lines, the line they come after.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_count: usize,
+    pub new_start: usize,
+    pub new_count: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+/// The changes to one file in a patch. A created file has no `old_path`
+/// and a deleted one no `new_path`; different paths are a rename. `index`
+/// is the abbreviated `old..new` blob hashes, only shown.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FilePatch {
+    pub old_path: Option<PathBuf>,
+    pub new_path: Option<PathBuf>,
+    pub old_mode: Option<u32>,
+    pub new_mode: Option<u32>,
+    pub index: Option<String>,
+    pub binary: bool,
+    pub hunks: Vec<Hunk>,
+}
+
+impl Hunk {
+    /// The lines the hunk expects and the lines it leaves, in order.
+    fn sides(&self) -> (Vec<&[u8]>, Vec<&[u8]>) {
+        let mut old = Vec::new();
+        let mut new = Vec::new();
+        for line in &self.lines {
+            match line {
+                HunkLine::Context(line) => {
+                    old.push(&line[..]);
+                    new.push(&line[..]);
+                }
+                HunkLine::Deleted(line) => old.push(line),
+                HunkLine::Added(line) => new.push(line),
+            }
+        }
+
+        (old, new)
+    }
+
+    /// The number of context lines before the first change and after the
+    /// last one.
+    fn context_ends(&self) -> (usize, usize) {
+        let is_context = |line: &&HunkLine| matches!(line, HunkLine::Context(_));
+        let leading = self.lines.iter().take_while(is_context).count();
+        let trailing = self.lines.iter().rev().take_while(is_context).count();
+
+        (leading, trailing)
+    }
+
+    /// Writes the hunk as in a unified diff, marking a last line without a
+    /// newline. Lines are written as they are, whatever their encoding.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "@@ -{} +{} @@",
+            hunk_range(self.old_start, self.old_count),
+            hunk_range(self.new_start, self.new_count)
+        )?;
+        for line in &self.lines {
+            let (prefix, content) = match line {
+                HunkLine::Context(content) => (b' ', content),
+                HunkLine::Deleted(content) => (b'-', content),
+                HunkLine::Added(content) => (b'+', content),
+            };
+            writer.write_all(&[prefix])?;
+            writer.write_all(content)?;
+            if !content.ends_with(b"\n") {
+                writeln!(writer)?;
+                writeln!(writer, "{}", NO_NEWLINE)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FilePatch {
+    /// The path the patch changes, or deletes.
+    pub fn path(&self) -> &Path {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .expect("a file patch has a path")
+    }
+
+    /// Writes the patch like `git diff`: the `diff --git` header, mode and
+    /// index lines, then the hunks.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let old_name = self.old_path.as_deref().unwrap_or_else(|| self.path());
+        let new_name = self.new_path.as_deref().unwrap_or_else(|| self.path());
+        writeln!(
+            writer,
+            "diff --git a/{} b/{}",
+            old_name.display(),
+            new_name.display()
+        )?;
+
+        let mut index_mode = None;
+        match (self.old_mode, self.new_mode) {
+            (None, Some(mode)) => writeln!(writer, "new file mode {:o}", mode)?,
+            (Some(mode), None) => writeln!(writer, "deleted file mode {:o}", mode)?,
+            (Some(old), Some(new)) if old != new => {
+                writeln!(writer, "old mode {:o}", old)?;
+                writeln!(writer, "new mode {:o}", new)?;
+            }
+            (mode, _) => index_mode = mode,
+        }
+        if old_name != new_name && self.old_path.is_some() && self.new_path.is_some() {
+            writeln!(writer, "rename from {}", old_name.display())?;
+            writeln!(writer, "rename to {}", new_name.display())?;
+        }
+        if let Some(index) = &self.index {
+            match index_mode {
+                Some(mode) => writeln!(writer, "index {} {:o}", index, mode)?,
+                None => writeln!(writer, "index {}", index)?,
+            }
+        }
+
+        let old_label = match &self.old_path {
+            Some(path) => format!("a/{}", path.display()),
+            None => "/dev/null".to_owned(),
+        };
+        let new_label = match &self.new_path {
+            Some(path) => format!("b/{}", path.display()),
+            None => "/dev/null".to_owned(),
+        };
+        if self.binary {
+            writeln!(
+                writer,
+                "Binary files {} and {} differ",
+                old_label, new_label
+            )?;
+        } else if !self.hunks.is_empty() {
+            writeln!(writer, "--- {}", old_label)?;
+            writeln!(writer, "+++ {}", new_label)?;
+            for hunk in &self.hunks {
+                hunk.write_to(writer)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Groups the changes from `old` to `new` into hunks with `context`
+/// unchanged lines around them; changes closer than twice that share a
+/// hunk.
+pub fn unified_hunks(old: &[u8], new: &[u8], context: usize) -> Vec<Hunk> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let ops = diff(&old_lines, &new_lines);
+
+    let mut groups = Vec::new();
+    let mut group = Vec::new();
+    let mut between = None;
+    for op in &ops {
+        match op {
+            DiffOp::Change { .. } => {
+                group.extend(between.take());
+                group.push(op);
+            }
+            DiffOp::Equal { old, .. } if !group.is_empty() && old.len() <= 2 * context => {
+                between = Some(op);
+            }
+            DiffOp::Equal { .. } => {
+                between = None;
+                if !group.is_empty() {
+                    groups.push(mem::take(&mut group));
+                }
+            }
+        }
+    }
+    if !group.is_empty() {
+        groups.push(group);
+    }
+
+    groups
+        .into_iter()
+        .map(|group| {
+            let (first_old, first_new) = match group[0] {
+                DiffOp::Change { old, new } | DiffOp::Equal { old, new } => (old.start, new.start),
+            };
+            let (last_old, last_new) = match group[group.len() - 1] {
+                DiffOp::Change { old, new } | DiffOp::Equal { old, new } => (old.end, new.end),
+            };
+            let before = context.min(first_old).min(first_new);
+            let after = context
+                .min(old_lines.len() - last_old)
+                .min(new_lines.len() - last_new);
+
+            let mut lines = Vec::new();
+            for line in &old_lines[first_old - before..first_old] {
+                lines.push(HunkLine::Context(line.to_vec()));
+            }
+            for op in &group {
+                match op {
+                    DiffOp::Equal { old, .. } => {
+                        for line in &old_lines[old.clone()] {
+                            lines.push(HunkLine::Context(line.to_vec()));
+                        }
+                    }
+                    DiffOp::Change { old, new } => {
+                        for line in &old_lines[old.clone()] {
+                            lines.push(HunkLine::Deleted(line.to_vec()));
+                        }
+                        for line in &new_lines[new.clone()] {
+                            lines.push(HunkLine::Added(line.to_vec()));
+                        }
+                    }
+                }
+            }
+            for line in &old_lines[last_old..last_old + after] {
+                lines.push(HunkLine::Context(line.to_vec()));
+            }
+
+            let old_count = last_old + after - (first_old - before);
+            let new_count = last_new + after - (first_new - before);
+            Hunk {
+                old_start: hunk_start(first_old - before, old_count),
+                old_count,
+                new_start: hunk_start(first_new - before, new_count),
+                new_count,
+                lines,
+            }
+        })
+        .collect()
+}
+
+/// Makes the patches for the entries of a tree diff, marking binary files
+/// as `is_binary_diff` does.
+pub fn tree_diff_patches(changes: &[TreeChange], config: &Config) -> Result<Vec<FilePatch>> {
+    let attributes = Attributes::from_config(config)?;
+
+    let mut patches = Vec::new();
+    for change in changes {
+        let (old, new) = match change {
+            TreeChange::Added(entry) => (None, Some(entry)),
+            TreeChange::Deleted(entry) => (Some(entry), None),
+            TreeChange::Modified { old, new }
+            | TreeChange::Renamed { old, new, .. }
+            | TreeChange::Copied { old, new, .. } => (Some(old), Some(new)),
+        };
+        let old_content = entry_content(old, config)?;
+        let new_content = entry_content(new, config)?;
+        let binary = is_binary_diff(change.path(), &old_content, &new_content, &attributes);
+        let short = |entry: Option<&TreeEntry>| match entry {
+            Some(entry) => entry.sha1().to_string()[..7].to_owned(),
+            None => "0".repeat(7),
+        };
+
+        patches.push(FilePatch {
+            old_path: old.map(|entry| entry.name().to_owned()),
+            new_path: new.map(|entry| entry.name().to_owned()),
+            old_mode: old.map(TreeEntry::st_mode),
+            new_mode: new.map(TreeEntry::st_mode),
+            index: Some(format!("{}..{}", short(old), short(new))),
+            binary,
+            hunks: if binary {
+                Vec::new()
+            } else {
+                unified_hunks(&old_content, &new_content, DEFAULT_CONTEXT)
+            },
+        });
+    }
+
+    Ok(patches)
+}
+
+/// Parses the file patches of a unified diff, with or without `git diff`
+/// headers. Lines around them, like a mail's, are skipped. Paths lose their
+/// first component, the `a/` and `b/` of `git diff`.
+pub fn parse_patch(text: &[u8]) -> Result<Vec<FilePatch>> {
+    let lines = split_lines(text);
+    let mut patches = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = String::from_utf8_lossy(lines[i]);
+        let line = line.trim_end_matches('\n');
+        if let Some(names) = line.strip_prefix("diff --git ") {
+            let mut patch = FilePatch::default();
+            if let Some((old, new)) = names.split_once(" b/") {
+                let old = old.strip_prefix("a/").unwrap_or(old);
+                patch.old_path = Some(PathBuf::from(old));
+                patch.new_path = Some(PathBuf::from(new));
+            }
+            i = parse_file_patch(&lines, i + 1, &mut patch)?;
+            patches.push(patch);
+        } else if line.starts_with("--- ")
+            && lines
+                .get(i + 1)
+                .is_some_and(|next| next.starts_with(b"+++ "))
+        {
+            let mut patch = FilePatch::default();
+            i = parse_file_patch(&lines, i, &mut patch)?;
+            patches.push(patch);
+        } else {
+            i += 1;
+        }
+    }
+
+    Ok(patches)
+}
+
+/// Parses the extended headers and hunks of a file patch from line `i`,
+/// returning the line after it.
+fn parse_file_patch(lines: &[&[u8]], mut i: usize, patch: &mut FilePatch) -> Result<usize> {
+    let mut created = false;
+    let mut deleted = false;
+    while i < lines.len() {
+        let line = String::from_utf8_lossy(lines[i]);
+        let line = line.trim_end_matches('\n');
+        if let Some(mode) = line.strip_prefix("new file mode ") {
+            created = true;
+            patch.new_mode = Some(parse_mode(mode)?);
+        } else if let Some(mode) = line.strip_prefix("deleted file mode ") {
+            deleted = true;
+            patch.old_mode = Some(parse_mode(mode)?);
+        } else if let Some(mode) = line.strip_prefix("old mode ") {
+            patch.old_mode = Some(parse_mode(mode)?);
+        } else if let Some(mode) = line.strip_prefix("new mode ") {
+            patch.new_mode = Some(parse_mode(mode)?);
+        } else if let Some(index) = line.strip_prefix("index ") {
+            let (hashes, mode) = index.split_once(' ').unwrap_or((index, ""));
+            patch.index = Some(hashes.to_owned());
+            if !mode.is_empty() {
+                patch.old_mode = Some(parse_mode(mode)?);
+                patch.new_mode = patch.old_mode;
+            }
+        } else if let Some(path) = line
+            .strip_prefix("rename from ")
+            .or_else(|| line.strip_prefix("copy from "))
+        {
+            patch.old_path = Some(PathBuf::from(path));
+        } else if let Some(path) = line
+            .strip_prefix("rename to ")
+            .or_else(|| line.strip_prefix("copy to "))
+        {
+            patch.new_path = Some(PathBuf::from(path));
+        } else if line.starts_with("similarity index ") || line.starts_with("dissimilarity index ")
+        {
+        } else if line.starts_with("Binary files ") {
+            patch.binary = true;
+        } else if let Some(name) = line.strip_prefix("--- ") {
+            patch.old_path = patch_path(name);
+            created |= patch.old_path.is_none();
+        } else if let Some(name) = line.strip_prefix("+++ ") {
+            patch.new_path = patch_path(name);
+            deleted |= patch.new_path.is_none();
+        } else if line.starts_with("@@ ") {
+            let (hunk, next) = parse_hunk(lines, i)?;
+            patch.hunks.push(hunk);
+            i = next;
+            continue;
+        } else {
+            break;
+        }
+        i += 1;
+    }
+
+    if created {
+        patch.old_path = None;
+    }
+    if deleted {
+        patch.new_path = None;
+    }
+    if patch.old_path.is_none() && patch.new_path.is_none() {
+        return Err(GitError::ParsePatchError(
+            "file patch without a path".to_owned(),
+        ));
+    }
+    for path in patch.old_path.iter().chain(&patch.new_path) {
+        if !worktree::is_valid_path(path) {
+            return Err(GitError::ParsePatchError(format!(
+                "invalid path {:?}",
+                path
+            )));
+        }
+    }
+
+    Ok(i)
+}
+
+/// Parses the hunk whose header is line `i`, returning it and the line
+/// after it.
+fn parse_hunk(lines: &[&[u8]], i: usize) -> Result<(Hunk, usize)> {
+    let header = String::from_utf8_lossy(lines[i]);
+    let header = header.trim_end_matches('\n');
+    let malformed = || GitError::ParsePatchError(header.to_owned());
+    let ranges = header
+        .strip_prefix("@@ -")
+        .and_then(|rest| rest.split_once(" @@"))
+        .map(|(ranges, _)| ranges)
+        .ok_or_else(malformed)?;
+    let (old, new) = ranges.split_once(" +").ok_or_else(malformed)?;
+    let (old_start, old_count) = parse_range(old).ok_or_else(malformed)?;
+    let (new_start, new_count) = parse_range(new).ok_or_else(malformed)?;
+
+    let mut hunk = Hunk {
+        old_start,
+        old_count,
+        new_start,
+        new_count,
+        lines: Vec::new(),
+    };
+    let (mut old_left, mut new_left) = (old_count, new_count);
+    let mut i = i + 1;
+    while i < lines.len() && (old_left > 0 || new_left > 0) {
+        let line = lines[i];
+        let content = line.get(1..).unwrap_or_default().to_vec();
+        match line.first() {
+            Some(b' ') if old_left > 0 && new_left > 0 => {
+                old_left -= 1;
+                new_left -= 1;
+                hunk.lines.push(HunkLine::Context(content));
+            }
+            // Some mailers strip the space of empty context lines.
+            Some(b'\n') if old_left > 0 && new_left > 0 => {
+                old_left -= 1;
+                new_left -= 1;
+                hunk.lines.push(HunkLine::Context(b"\n".to_vec()));
+            }
+            Some(b'-') if old_left > 0 => {
+                old_left -= 1;
+                hunk.lines.push(HunkLine::Deleted(content));
+            }
+            Some(b'+') if new_left > 0 => {
+                new_left -= 1;
+                hunk.lines.push(HunkLine::Added(content));
+            }
+            Some(b'\\') => strip_newline(&mut hunk),
+            _ => {
+                return Err(GitError::ParsePatchError(format!(
+                    "truncated hunk {}",
+                    header
+                )))
+            }
+        }
+        i += 1;
+    }
+    if old_left > 0 || new_left > 0 {
+        return Err(GitError::ParsePatchError(format!(
+            "truncated hunk {}",
+            header
+        )));
+    }
+    if lines.get(i).is_some_and(|line| line.starts_with(b"\\")) {
+        strip_newline(&mut hunk);
+        i += 1;
+    }
+
+    Ok((hunk, i))
+}
+
+/// Applies `hunks` to `content` in order. A hunk that does not match where
+/// it says is looked for nearby, then with up to `MAX_FUZZ` of its context
+/// lines at either end ignored. Returns `None` when a hunk cannot be
+/// placed.
+pub fn apply_hunks(content: &[u8], hunks: &[Hunk]) -> Option<Vec<u8>> {
+    let lines = split_lines(content);
+    let mut result = Vec::new();
+    let mut pos = 0;
+    let mut offset = 0_isize;
+
+    for (n, hunk) in hunks.iter().enumerate() {
+        let (old, new) = hunk.sides();
+        let (leading, trailing) = hunk.context_ends();
+        let expected = if hunk.old_count == 0 {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+
+        let placed = (0..=MAX_FUZZ).find_map(|fuzz| {
+            let (skip_front, skip_back) = (fuzz.min(leading), fuzz.min(trailing));
+            if skip_front + skip_back > old.len() {
+                return None;
+            }
+            let old = &old[skip_front..old.len() - skip_back];
+            let start = (expected + skip_front) as isize + offset;
+            find_lines(&lines, old, pos, start).map(|at| (at, fuzz, skip_front, skip_back))
+        });
+        let (at, fuzz, skip_front, skip_back) = placed?;
+        let shift = at as isize - (expected + skip_front) as isize;
+        if shift != 0 || fuzz > 0 {
+            debug!(
+                "hunk #{} applied with offset {} fuzz {}",
+                n + 1,
+                shift,
+                fuzz
+            );
+        }
+
+        for line in &lines[pos..at] {
+            result.extend_from_slice(line);
+        }
+        for line in &new[skip_front..new.len() - skip_back] {
+            result.extend_from_slice(line);
+        }
+        pos = at + old.len() - skip_front - skip_back;
+        offset = shift;
+    }
+    for line in &lines[pos..] {
+        result.extend_from_slice(line);
+    }
+
+    Some(result)
+}
+
+/// Finds `wanted` in `lines` at or after `min`, trying `start` first and
+/// then ever farther on either side of it.
+fn find_lines(lines: &[&[u8]], wanted: &[&[u8]], min: usize, start: isize) -> Option<usize> {
+    let max = lines.len().checked_sub(wanted.len())?;
+    if min > max {
+        return None;
+    }
+    let start = start.clamp(min as isize, max as isize) as usize;
+    let matches = |at: usize| lines[at..at + wanted.len()] == *wanted;
+
+    for distance in 0..=(max - min) {
+        if start >= min + distance && matches(start - distance) {
+            return Some(start - distance);
+        }
+        if distance > 0 && start + distance <= max && matches(start + distance) {
+            return Some(start + distance);
+        }
+    }
+
+    None
+}
+
+/// Drops the newline of the hunk's last line, as a `\ No newline at end of
+/// file` marker after it says.
+fn strip_newline(hunk: &mut Hunk) {
+    if let Some(HunkLine::Context(line) | HunkLine::Deleted(line) | HunkLine::Added(line)) =
+        hunk.lines.last_mut()
+    {
+        if line.ends_with(b"\n") {
+            let _ = line.pop();
+        }
+    }
+}
+
+fn hunk_start(index: usize, count: usize) -> usize {
+    if count == 0 {
+        index
+    } else {
+        index + 1
+    }
+}
+
+fn hunk_range(start: usize, count: usize) -> String {
+    if count == 1 {
+        start.to_string()
+    } else {
+        format!("{},{}", start, count)
+    }
+}
+
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<u32> {
+    u32::from_str_radix(mode.trim(), 8)
+        .map_err(|_| GitError::ParsePatchError(format!("invalid mode {}", mode)))
+}
+
+/// The path of a `---` or `+++` line without its first component and any
+/// tab-separated timestamp, or `None` for `/dev/null`.
+fn patch_path(name: &str) -> Option<PathBuf> {
+    let name = name.split('\t').next().unwrap_or(name).trim_end();
+    if name == "/dev/null" {
+        return None;
+    }
+
+    let path = Path::new(name);
+    let stripped = path.iter().skip(1).collect::<PathBuf>();
+    if stripped.as_os_str().is_empty() {
+        Some(path.to_owned())
+    } else {
+        Some(stripped)
+    }
+}
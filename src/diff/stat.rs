@@ -0,0 +1,213 @@
+use std::path::Path;
+
+use super::{diff, split_lines, DiffOp, TreeChange};
+use crate::{attributes::Attributes, merge::read_blob, Config, Result, TreeEntry};
+
+/// How many leading bytes `is_binary` looks at, as git does.
+pub const BINARY_CHECK_SIZE: usize = 8000;
+/// The widest `+`/`-` bar `--stat` draws; longer ones are scaled down.
+const MAX_BAR_WIDTH: usize = 50;
+
+/// The summaries of a diff the `--stat`, `--numstat` and `--shortstat`
+/// options print.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatFormat {
+    Stat,
+    Numstat,
+    Shortstat,
+}
+
+/// How much a file changed: lines for text, sizes for binary files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineChanges {
+    Text { added: usize, deleted: usize },
+    Binary { old_size: usize, new_size: usize },
+}
+
+/// The changes to one file, named as the stat output shows it.
+#[derive(Debug)]
+pub struct FileStat {
+    pub name: String,
+    pub changes: LineChanges,
+}
+
+impl FileStat {
+    /// Counts the lines added and deleted from `old` to `new`, or only their
+    /// sizes when `binary`.
+    pub fn new(name: String, old: &[u8], new: &[u8], binary: bool) -> Self {
+        let changes = if binary {
+            LineChanges::Binary {
+                old_size: old.len(),
+                new_size: new.len(),
+            }
+        } else {
+            let (mut added, mut deleted) = (0, 0);
+            for op in diff(&split_lines(old), &split_lines(new)) {
+                if let DiffOp::Change { old, new } = op {
+                    added += new.len();
+                    deleted += old.len();
+                }
+            }
+            LineChanges::Text { added, deleted }
+        };
+
+        Self { name, changes }
+    }
+}
+
+/// Whether `content` looks binary: it has a NUL byte near the start.
+pub fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(BINARY_CHECK_SIZE).any(|&c| c == 0)
+}
+
+/// Whether the file at `path` should be diffed as binary, going by
+/// `attributes` first and the contents of both versions otherwise.
+pub fn is_binary_diff(path: &Path, old: &[u8], new: &[u8], attributes: &Attributes) -> bool {
+    attributes
+        .is_binary(path)
+        .unwrap_or_else(|| is_binary(old) || is_binary(new))
+}
+
+/// Counts the changes of each entry of a tree diff. Renames and copies are
+/// named `old => new`.
+pub fn tree_diff_stats(changes: &[TreeChange], config: &Config) -> Result<Vec<FileStat>> {
+    let attributes = Attributes::from_config(config)?;
+
+    let mut stats = Vec::new();
+    for change in changes {
+        let (old, new, name) = match change {
+            TreeChange::Added(entry) => (None, Some(entry), entry.name().display().to_string()),
+            TreeChange::Deleted(entry) => (Some(entry), None, entry.name().display().to_string()),
+            TreeChange::Modified { old, new } => {
+                (Some(old), Some(new), new.name().display().to_string())
+            }
+            TreeChange::Renamed { old, new, .. } | TreeChange::Copied { old, new, .. } => (
+                Some(old),
+                Some(new),
+                format!("{} => {}", old.name().display(), new.name().display()),
+            ),
+        };
+
+        let old = entry_content(old, config)?;
+        let new = entry_content(new, config)?;
+        let binary = is_binary_diff(change.path(), &old, &new, &attributes);
+        stats.push(FileStat::new(name, &old, &new, binary));
+    }
+
+    Ok(stats)
+}
+
+/// Formats `stats` for `format`. `Stat` lists each file with its number of
+/// changed lines and a bar of `+` and `-`, then the `Shortstat` summary;
+/// `Numstat` gives added and deleted lines, or `-` for binary files.
+pub fn format_stats(stats: &[FileStat], format: StatFormat) -> Vec<String> {
+    if stats.is_empty() {
+        return Vec::new();
+    }
+
+    match format {
+        StatFormat::Numstat => stats
+            .iter()
+            .map(|stat| match stat.changes {
+                LineChanges::Text { added, deleted } => {
+                    format!("{}\t{}\t{}", added, deleted, stat.name)
+                }
+                LineChanges::Binary { .. } => format!("-\t-\t{}", stat.name),
+            })
+            .collect(),
+        StatFormat::Shortstat => vec![summary(stats)],
+        StatFormat::Stat => {
+            let name_width = stats.iter().map(|stat| stat.name.len()).max().unwrap_or(0);
+            let max_changed = stats
+                .iter()
+                .map(|stat| match stat.changes {
+                    LineChanges::Text { added, deleted } => added + deleted,
+                    LineChanges::Binary { .. } => 0,
+                })
+                .max()
+                .unwrap_or(0);
+            let count_width = max_changed.to_string().len();
+
+            let mut lines = stats
+                .iter()
+                .map(|stat| match stat.changes {
+                    LineChanges::Text { added, deleted } => {
+                        let (plus, minus) = if max_changed > MAX_BAR_WIDTH {
+                            let scale =
+                                |n: usize| (n * MAX_BAR_WIDTH + max_changed - 1) / max_changed;
+                            (scale(added), scale(deleted))
+                        } else {
+                            (added, deleted)
+                        };
+                        format!(
+                            " {:name_width$} | {:>count_width$} {}{}",
+                            stat.name,
+                            added + deleted,
+                            "+".repeat(plus),
+                            "-".repeat(minus),
+                            name_width = name_width,
+                            count_width = count_width
+                        )
+                        .trim_end()
+                        .to_owned()
+                    }
+                    LineChanges::Binary { old_size, new_size } => format!(
+                        " {:name_width$} | Bin {} -> {} bytes",
+                        stat.name,
+                        old_size,
+                        new_size,
+                        name_width = name_width
+                    ),
+                })
+                .collect::<Vec<_>>();
+            lines.push(summary(stats));
+            lines
+        }
+    }
+}
+
+/// ` N files changed, X insertions(+), Y deletions(-)`, leaving out a zero
+/// count unless both are zero.
+fn summary(stats: &[FileStat]) -> String {
+    let (mut added, mut deleted) = (0, 0);
+    for stat in stats {
+        if let LineChanges::Text {
+            added: a,
+            deleted: d,
+        } = stat.changes
+        {
+            added += a;
+            deleted += d;
+        }
+    }
+
+    let plural =
+        |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+    let mut line = format!(" {}", plural(stats.len(), "file changed", "files changed"));
+    if added > 0 || deleted == 0 {
+        line.push_str(&format!(
+            ", {}",
+            plural(added, "insertion(+)", "insertions(+)")
+        ));
+    }
+    if deleted > 0 || added == 0 {
+        line.push_str(&format!(
+            ", {}",
+            plural(deleted, "deletion(-)", "deletions(-)")
+        ));
+    }
+
+    line
+}
+
+/// Reads what a diff compares for `entry`: the blob, or for a gitlink the
+/// line git shows for it. A missing entry is empty.
+pub(super) fn entry_content(entry: Option<&TreeEntry>, config: &Config) -> Result<Vec<u8>> {
+    match entry {
+        Some(entry) if entry.is_gitlink() => {
+            Ok(format!("Subproject commit {}\n", entry.sha1()).into_bytes())
+        }
+        Some(entry) => read_blob(entry.sha1(), config),
+        None => Ok(Vec::new()),
+    }
+}
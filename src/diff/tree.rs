@@ -0,0 +1,297 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap},
+    fmt,
+    path::Path,
+};
+
+use tracing::error;
+
+use super::{is_binary, matching_lines, split_lines};
+use crate::{merge::read_blob, Config, Result, Tree, TreeEntry};
+
+/// The default similarity, in percent, above which a deleted and an added
+/// file are paired as a rename.
+pub const DEFAULT_RENAME_THRESHOLD: u32 = 50;
+/// The default of `RenameOptions::limit`, as git's `diff.renameLimit`.
+pub const DEFAULT_RENAME_LIMIT: usize = 1000;
+
+/// How a path differs between two trees.
+#[derive(Clone, Debug)]
+pub enum TreeChange {
+    Added(TreeEntry),
+    Deleted(TreeEntry),
+    Modified {
+        old: TreeEntry,
+        new: TreeEntry,
+    },
+    /// `old` was deleted and `new` added with content `score` percent alike.
+    Renamed {
+        old: TreeEntry,
+        new: TreeEntry,
+        score: u32,
+    },
+    /// `new` was added with content `score` percent alike to `old`, which
+    /// still exists.
+    Copied {
+        old: TreeEntry,
+        new: TreeEntry,
+        score: u32,
+    },
+}
+
+/// Which moves `detect_renames` looks for; looking for copies implies
+/// renames. Files are paired when their similarity is at least `threshold`
+/// percent, unless comparing them all would take more than `limit` squared
+/// pairs.
+#[derive(Clone, Copy, Debug)]
+pub struct RenameOptions {
+    pub renames: bool,
+    pub copies: bool,
+    pub threshold: u32,
+    pub limit: usize,
+}
+
+impl Default for RenameOptions {
+    fn default() -> Self {
+        Self {
+            renames: true,
+            copies: false,
+            threshold: DEFAULT_RENAME_THRESHOLD,
+            limit: DEFAULT_RENAME_LIMIT,
+        }
+    }
+}
+
+impl TreeChange {
+    /// The status letter `git diff --name-status` shows.
+    pub fn status(&self) -> char {
+        match self {
+            TreeChange::Added(_) => 'A',
+            TreeChange::Deleted(_) => 'D',
+            TreeChange::Modified { .. } => 'M',
+            TreeChange::Renamed { .. } => 'R',
+            TreeChange::Copied { .. } => 'C',
+        }
+    }
+
+    /// The path the change leaves in the new tree, or the deleted path.
+    pub fn path(&self) -> &Path {
+        match self {
+            TreeChange::Deleted(entry) | TreeChange::Added(entry) => entry.name(),
+            TreeChange::Modified { new, .. }
+            | TreeChange::Renamed { new, .. }
+            | TreeChange::Copied { new, .. } => new.name(),
+        }
+    }
+
+    /// The path the change starts from, if it had one in the old tree.
+    pub fn old_path(&self) -> Option<&Path> {
+        match self {
+            TreeChange::Added(_) => None,
+            TreeChange::Deleted(entry) => Some(entry.name()),
+            TreeChange::Modified { old, .. }
+            | TreeChange::Renamed { old, .. }
+            | TreeChange::Copied { old, .. } => Some(old.name()),
+        }
+    }
+}
+
+/// Formats the change like `git diff --name-status`: the status letter,
+/// with the score for renames and copies, and the paths, tab separated.
+impl fmt::Display for TreeChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeChange::Renamed { old, new, score } | TreeChange::Copied { old, new, score } => {
+                write!(
+                    f,
+                    "{}{:03}\t{}\t{}",
+                    self.status(),
+                    score,
+                    old.name().display(),
+                    new.name().display()
+                )
+            }
+            _ => write!(f, "{}\t{}", self.status(), self.path().display()),
+        }
+    }
+}
+
+/// Lists the paths whose entries differ between `old` and `new`, in path
+/// order. A moved file shows up as a deletion and an addition.
+pub fn diff_trees(old: &Tree, new: &Tree) -> Vec<TreeChange> {
+    let names = old
+        .entries
+        .keys()
+        .chain(new.entries.keys())
+        .collect::<BTreeSet<_>>();
+
+    names
+        .into_iter()
+        .filter_map(
+            |name| match (old.entries.get(name), new.entries.get(name)) {
+                (Some(o), Some(n)) if o.sha1() == n.sha1() && o.st_mode() == n.st_mode() => None,
+                (Some(o), Some(n)) => Some(TreeChange::Modified {
+                    old: o.clone(),
+                    new: n.clone(),
+                }),
+                (Some(o), None) => Some(TreeChange::Deleted(o.clone())),
+                (None, Some(n)) => Some(TreeChange::Added(n.clone())),
+                (None, None) => None,
+            },
+        )
+        .collect()
+}
+
+/// Pairs added files in `changes` with deleted files, or with the old side
+/// of modified files for copies, whose content is alike. Only files of the
+/// same type are paired. Identical blobs are paired first; the rest are
+/// scored by the share of bytes in lines the two versions have in common,
+/// leaving out binary files.
+pub fn detect_renames(
+    changes: Vec<TreeChange>,
+    options: &RenameOptions,
+    config: &Config,
+) -> Result<Vec<TreeChange>> {
+    if !options.renames && !options.copies {
+        return Ok(changes);
+    }
+
+    let mut added = Vec::new();
+    let mut deleted = Vec::new();
+    let mut sources = Vec::new();
+    let mut result = Vec::new();
+    for change in changes {
+        match change {
+            TreeChange::Added(entry) if !entry.is_gitlink() => added.push(Some(entry)),
+            TreeChange::Deleted(entry) if !entry.is_gitlink() => deleted.push(Some(entry)),
+            TreeChange::Modified { old, new } => {
+                if options.copies && !old.is_gitlink() {
+                    sources.push(old.clone());
+                }
+                result.push(TreeChange::Modified { old, new });
+            }
+            change => result.push(change),
+        }
+    }
+
+    // Exact renames, preferring sources with the same file name.
+    for new_slot in added.iter_mut() {
+        let new = new_slot.as_ref().expect("added files are unpaired");
+        let same_blob = |old: &&mut Option<TreeEntry>| {
+            old.as_ref()
+                .is_some_and(|old| old.sha1() == new.sha1() && same_type(old, new))
+        };
+        let candidates = deleted.iter_mut().filter(same_blob).collect::<Vec<_>>();
+        let chosen = candidates.into_iter().min_by_key(|old| {
+            old.as_ref()
+                .map(|old| old.name().file_name() != new.name().file_name())
+        });
+        if let Some(old_slot) = chosen {
+            let old = old_slot.take().expect("deleted files are unpaired");
+            let new = new_slot.take().expect("added files are unpaired");
+            if options.copies {
+                sources.push(old.clone());
+            }
+            result.push(TreeChange::Renamed {
+                old,
+                new,
+                score: 100,
+            });
+        }
+    }
+
+    // Content similarity for the rest, unless there are too many to compare.
+    let unpaired_new = added.iter().flatten().count();
+    let unpaired_old = deleted.iter().flatten().count() + sources.len();
+    let within_limit =
+        unpaired_new.saturating_mul(unpaired_old) <= options.limit.saturating_mul(options.limit);
+    if !within_limit {
+        error!(
+            "skipped inexact rename detection of {} files against {}, over the limit of {}",
+            unpaired_new, unpaired_old, options.limit
+        );
+    }
+    let mut blobs = HashMap::new();
+    let mut scores = Vec::new();
+    for (i, new) in added.iter().enumerate() {
+        let new = match new {
+            Some(new) if within_limit => new,
+            _ => continue,
+        };
+        let olds = deleted
+            .iter()
+            .enumerate()
+            .filter_map(|(j, old)| old.as_ref().map(|old| (Some(j), old)))
+            .chain(sources.iter().map(|old| (None, old)))
+            .filter(|(_, old)| same_type(old, new));
+        for (j, old) in olds {
+            let score = if old.sha1() == new.sha1() {
+                100
+            } else {
+                for sha1 in [old.sha1(), new.sha1()] {
+                    if !blobs.contains_key(sha1) {
+                        let _ = blobs.insert(sha1.clone(), read_blob(sha1, config)?);
+                    }
+                }
+                let (old_content, new_content) = (&blobs[old.sha1()], &blobs[new.sha1()]);
+                if is_binary(old_content) || is_binary(new_content) {
+                    continue;
+                }
+                similarity(old_content, new_content)
+            };
+            if score >= options.threshold {
+                scores.push((score, i, j, old.clone()));
+            }
+        }
+    }
+    scores.sort_by_key(|(score, i, _, old)| (Reverse(*score), *i, old.name().to_owned()));
+
+    for (score, i, j, old) in scores {
+        if added[i].is_none() {
+            continue;
+        }
+        match j {
+            Some(j) => {
+                if deleted[j].take().is_none() {
+                    continue;
+                }
+                let new = added[i].take().expect("added files are unpaired");
+                result.push(TreeChange::Renamed { old, new, score });
+            }
+            None => {
+                let new = added[i].take().expect("added files are unpaired");
+                result.push(TreeChange::Copied { old, new, score });
+            }
+        }
+    }
+
+    result.extend(added.into_iter().flatten().map(TreeChange::Added));
+    result.extend(deleted.into_iter().flatten().map(TreeChange::Deleted));
+    result.sort_by(|a, b| a.path().cmp(b.path()));
+
+    Ok(result)
+}
+
+/// Whether `old` and `new` are both symlinks or both files.
+fn same_type(old: &TreeEntry, new: &TreeEntry) -> bool {
+    old.is_symlink() == new.is_symlink()
+}
+
+/// Scores how alike two contents are, in percent: the bytes of the lines
+/// they share over the size of the larger one.
+pub fn similarity(old: &[u8], new: &[u8]) -> u32 {
+    let max = old.len().max(new.len());
+    if max == 0 {
+        return 100;
+    }
+
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let common = matching_lines(&old_lines, &new_lines)
+        .into_iter()
+        .map(|(i, _)| old_lines[i].len())
+        .sum::<usize>();
+
+    (common * 100 / max) as u32
+}
@@ -1,4 +1,4 @@
-use std::{io, result};
+use std::{io, path::PathBuf, result};
 
 use crate::Sha1Hash;
 
@@ -15,10 +15,81 @@ pub enum GitError {
 
     #[error("{0} is not a valid object type")]
     ParseObjectError(String),
+    #[error("malformed {0} object: {1:?}")]
+    MalformedObject(String, String),
     #[error("Not a sha1 hash")]
     SizeNotMatch,
+    #[error("expected {0} bytes but read {1}")]
+    SizeChanged(u64, u64),
+    #[error("content hashed as {0} changed while being stored")]
+    ContentChanged(Sha1Hash),
     #[error("{0} is not a tree object")]
     NotTreeError(Sha1Hash),
     #[error("{0} is a tree object")]
     IsTreeError(Sha1Hash),
+    #[error("{0} is not a {1} object")]
+    WrongObjectType(Sha1Hash, String),
+    #[error("{0} is not a commit object")]
+    NotCommitError(Sha1Hash),
+    #[error("{0} does not exist")]
+    MissingObject(Sha1Hash),
+    #[error("{0:?} does not exist in {1}")]
+    PathNotInTree(PathBuf, Sha1Hash),
+    #[error("{0:?} is not a valid path in the work tree")]
+    InvalidPath(PathBuf),
+    #[error("{0:?} is beyond a symbolic link")]
+    BeyondSymlink(PathBuf),
+    #[error("{0} is not a valid ref name")]
+    InvalidRefName(String),
+    #[error("{0:?} is not a valid reflog entry")]
+    ParseReflogError(String),
+    #[error("{0:?} is not a valid submodule manifest line")]
+    ParseManifestError(String),
+    #[error("local changes to {0:?} would be overwritten")]
+    LocalChanges(Vec<PathBuf>),
+    #[error("{0} already exists")]
+    RefExists(String),
+    #[error("no local changes to save")]
+    NoLocalChanges,
+    #[error("nothing to commit")]
+    NothingToCommit,
+    #[error("aborting commit due to empty commit message")]
+    EmptyCommitMessage,
+    #[error("editor {0:?} failed")]
+    EditorFailed(String),
+    #[error("{0:?} is not a valid todo line")]
+    ParseTodoError(String),
+    #[error("the bad commit {0} is reachable from a good commit")]
+    BisectBadIsGood(Sha1Hash),
+    #[error("bisect run command {0:?} failed")]
+    BisectRunFailed(String),
+    #[error("no {0} in progress")]
+    NoOperation(String),
+    #[error("a {0} is already in progress")]
+    OperationInProgress(String),
+    #[error("unresolved conflicts in {0:?}")]
+    UnresolvedConflicts(Vec<PathBuf>),
+    #[error("malformed patch: {0}")]
+    ParsePatchError(String),
+    #[error("patch does not apply to {0:?}")]
+    PatchDoesNotApply(PathBuf),
+    #[error("cannot apply binary patch to {0:?}")]
+    BinaryPatch(PathBuf),
+    #[error("{0} changes the binary file {1:?}, which a patch cannot carry")]
+    BinaryChange(Sha1Hash, PathBuf),
+    #[error("{0} is not a valid revision")]
+    UnknownRevision(String),
+    #[error("{0} was updated concurrently")]
+    StaleRef(String),
+    #[error("protocol error: {0}")]
+    ProtocolError(String),
+    #[error("corrupt pack: {0}")]
+    PackError(String),
+    #[error("invalid bundle: {0}")]
+    BundleError(String),
+    #[error(
+        "missing prerequisite commits {}",
+        .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    MissingPrerequisites(Vec<Sha1Hash>),
 }
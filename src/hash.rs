@@ -10,10 +10,14 @@ use serde::{Deserialize, Serialize};
 
 use crate::GitError;
 
-#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct Sha1Hash([u8; 20]);
 
 impl Sha1Hash {
+    pub fn is_null(&self) -> bool {
+        self.0.iter().all(|&b| b == 0)
+    }
+
     pub fn sha1_file_name(&self) -> PathBuf {
         let hex_str = encode(self);
         PathBuf::from(&hex_str[..2]).join(&hex_str[2..])
@@ -0,0 +1,201 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{config::REPO_DIRECTORY, Config, Result};
+
+pub const IGNORE_FILE: &str = ".dircacheignore";
+
+/// The patterns of a `.dircacheignore` file, using the gitignore syntax:
+/// `#` comments, `!` negation, a trailing `/` for directories only, and a
+/// leading or inner `/` to anchor a pattern to the top of the work tree.
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Debug)]
+struct Pattern {
+    glob: String,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl IgnoreRules {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        match fs::read_to_string(config.work_dir.join(IGNORE_FILE)) {
+            Ok(content) => Ok(Self::parse(&content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let patterns = content
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (negated, line) = match line.strip_prefix('!') {
+                    Some(line) => (true, line),
+                    None => (false, line),
+                };
+                let (dir_only, line) = match line.strip_suffix('/') {
+                    Some(line) => (true, line),
+                    None => (false, line),
+                };
+                let anchored = line.contains('/');
+
+                Pattern {
+                    glob: line.trim_start_matches('/').to_owned(),
+                    negated,
+                    dir_only,
+                    anchored,
+                }
+            })
+            .collect();
+
+        Self { patterns }
+    }
+
+    /// Whether `path`, relative to the top of the work tree, is ignored. The
+    /// last matching pattern wins.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if path.file_name().is_some_and(|name| name == REPO_DIRECTORY) {
+            return true;
+        }
+
+        let full_path = path.to_string_lossy();
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| {
+                let text = if pattern.anchored {
+                    &full_path
+                } else {
+                    &file_name
+                };
+                (is_dir || !pattern.dir_only)
+                    && glob_match(pattern.glob.as_bytes(), text.as_bytes())
+            })
+            .is_some_and(|pattern| !pattern.negated)
+    }
+
+    /// Expands directories in `paths` into the files below them, skipping
+    /// ignored entries and the repository directory. Nested repositories are
+    /// kept as a single path, and other paths as given. The result is sorted.
+    pub fn expand_paths(&self, paths: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for path in paths {
+            let path = normalize_path(&path);
+            if path.as_os_str().is_empty() || fs::symlink_metadata(&path)?.is_dir() {
+                self.walk_dir(&path, &mut files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        files.sort();
+        files.dedup();
+
+        Ok(files)
+    }
+
+    fn walk_dir(&self, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        let read_path = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+
+        for entry in fs::read_dir(read_path)? {
+            let entry = entry?;
+            let path = dir.join(entry.file_name());
+            let is_dir = entry.file_type()?.is_dir();
+            if self.is_ignored(&path, is_dir) {
+                continue;
+            }
+
+            if is_dir && !path.join(REPO_DIRECTORY).is_dir() {
+                self.walk_dir(&path, files)?;
+            } else {
+                files.push(path);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Drops `.` components so `./src/main.rs` and `src/main.rs` name the same
+/// cache entry.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+pub(crate) fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => match &pattern[2..] {
+            [b'/', rest @ ..] => {
+                glob_match(rest, text)
+                    || (0..text.len())
+                        .filter(|&i| text[i] == b'/')
+                        .any(|i| glob_match(rest, &text[i + 1..]))
+            }
+            rest => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        },
+        Some(b'*') => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob_match(&pattern[1..], &text[i..])),
+        Some(b'?') => match text.first() {
+            Some(&c) if c != b'/' => glob_match(&pattern[1..], &text[1..]),
+            _ => false,
+        },
+        Some(b'[') => match (
+            text.first(),
+            pattern.iter().skip(2).position(|&c| c == b']'),
+        ) {
+            (Some(&c), Some(end)) if c != b'/' => {
+                let class = &pattern[1..end + 2];
+                class_match(class, c) && glob_match(&pattern[end + 3..], &text[1..])
+            }
+            (Some(&b'['), None) => glob_match(&pattern[1..], &text[1..]),
+            _ => false,
+        },
+        Some(b'\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && glob_match(&pattern[2..], &text[1..])
+        }
+        Some(&c) => text.first() == Some(&c) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+fn class_match(class: &[u8], c: u8) -> bool {
+    let (negated, class) = match class.first() {
+        Some(b'!') | Some(b'^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+
+    let mut matched = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == b'-' {
+            matched |= class[i] <= c && c <= class[i + 2];
+            i += 3;
+        } else {
+            matched |= class[i] == c;
+            i += 1;
+        }
+    }
+
+    matched != negated
+}
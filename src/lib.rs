@@ -1,15 +1,31 @@
 #![warn(unused_results, missing_debug_implementations)]
 
+mod attributes;
 mod cache;
 mod command;
 mod config;
+pub mod diff;
 mod error;
 mod hash;
+mod ignore;
+pub mod merge;
 mod object;
+mod pack;
+pub mod reflog;
+pub mod refs;
+pub mod revision;
+pub mod transport;
+mod walk;
+mod worktree;
 
-pub use cache::{Cache, CacheEntry};
+pub use attributes::{Attributes, ATTRIBUTES_FILE};
+pub use cache::{Cache, CacheEntry, CacheLock};
 pub use command::*;
 pub use config::Config;
 pub use error::{GitError, Result};
 pub use hash::Sha1Hash;
-pub use object::{Blob, Commit, GitObject, Tree, TreeEntry};
+pub use object::{
+    ident_date, ident_timestamp, split_ident, Blob, BlobReader, Commit, GitObject, ObjectStream,
+    Tree, TreeEntry, DIRECTORY_MODE, EXECUTABLE_MODE, GITLINK_MODE, REGULAR_FILE_MODE,
+    SYMLINK_MODE,
+};
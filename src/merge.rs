@@ -0,0 +1,201 @@
+use std::{
+    collections::BTreeSet,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+use tracing::debug;
+
+use crate::{
+    diff::{matching_lines, split_lines},
+    Config, GitObject, Result, Sha1Hash, Tree, TreeEntry,
+};
+
+/// The result of a three-way merge of trees. Conflicting files are stored
+/// in `tree` with conflict markers, or as the side that still has them for
+/// modify/delete conflicts, and listed in `conflicts`.
+#[derive(Debug)]
+pub struct MergeOutcome {
+    pub tree: Tree,
+    pub conflicts: Vec<PathBuf>,
+}
+
+/// Merges the changes from `base` to `theirs` into `ours`, path by path,
+/// merging the lines of files both sides changed. `labels` name ours and
+/// theirs in conflict markers.
+pub fn merge_trees(
+    base: &Tree,
+    ours: &Tree,
+    theirs: &Tree,
+    labels: (&str, &str),
+    config: &Config,
+) -> Result<MergeOutcome> {
+    let names = base
+        .entries
+        .keys()
+        .chain(ours.entries.keys())
+        .chain(theirs.entries.keys())
+        .collect::<BTreeSet<_>>();
+
+    let mut tree = Tree::default();
+    let mut conflicts = Vec::new();
+    for name in names {
+        let b = base.entries.get(name);
+        let o = ours.entries.get(name);
+        let t = theirs.entries.get(name);
+
+        let merged = if same(o, t) || same(b, t) {
+            o.cloned()
+        } else if same(b, o) {
+            t.cloned()
+        } else {
+            match (o, t) {
+                (Some(o), Some(t)) if is_mergeable(o) && is_mergeable(t) => {
+                    let (entry, clean) = merge_entries(name, b, o, t, labels, config)?;
+                    if !clean {
+                        conflicts.push(name.clone());
+                    }
+                    Some(entry)
+                }
+                _ => {
+                    debug!("{:?} was changed differently on both sides", name);
+                    conflicts.push(name.clone());
+                    o.or(t).cloned()
+                }
+            }
+        };
+
+        if let Some(entry) = merged {
+            let _ = tree.entries.insert(name.clone(), entry);
+        }
+    }
+
+    Ok(MergeOutcome { tree, conflicts })
+}
+
+/// Merges the lines of `ours` and `theirs` against `base`. Returns the
+/// merged content and whether it is free of conflicts.
+pub fn merge_blobs(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    labels: (&str, &str),
+) -> (Vec<u8>, bool) {
+    let base = split_lines(base);
+    let ours = split_lines(ours);
+    let theirs = split_lines(theirs);
+
+    let mut ours_of = vec![None; base.len()];
+    for (i, j) in matching_lines(&base, &ours) {
+        ours_of[i] = Some(j);
+    }
+    let mut theirs_of = vec![None; base.len()];
+    for (i, j) in matching_lines(&base, &theirs) {
+        theirs_of[i] = Some(j);
+    }
+
+    let mut merged = Vec::new();
+    let mut clean = true;
+    let (mut ib, mut io, mut it) = (0, 0, 0);
+    loop {
+        let stable = (0..)
+            .take_while(|&i| {
+                ib + i < base.len()
+                    && ours_of[ib + i] == Some(io + i)
+                    && theirs_of[ib + i] == Some(it + i)
+            })
+            .count();
+        if stable > 0 {
+            merged.extend(base[ib..ib + stable].concat());
+            ib += stable;
+            io += stable;
+            it += stable;
+            continue;
+        }
+
+        let next = (ib..base.len()).find_map(|i| match (ours_of[i], theirs_of[i]) {
+            (Some(o), Some(t)) => Some((i, o, t)),
+            _ => None,
+        });
+        let (jb, jo, jt) = next.unwrap_or((base.len(), ours.len(), theirs.len()));
+        if jb == ib && jo == io && jt == it {
+            break;
+        }
+
+        let b = &base[ib..jb];
+        let o = &ours[io..jo];
+        let t = &theirs[it..jt];
+        if o == b || o == t {
+            merged.extend(t.concat());
+        } else if t == b {
+            merged.extend(o.concat());
+        } else {
+            clean = false;
+            write_marker(&mut merged, '<', Some(labels.0));
+            merged.extend(o.concat());
+            write_marker(&mut merged, '=', None);
+            merged.extend(t.concat());
+            write_marker(&mut merged, '>', Some(labels.1));
+        }
+        ib = jb;
+        io = jo;
+        it = jt;
+    }
+
+    (merged, clean)
+}
+
+fn write_marker(merged: &mut Vec<u8>, marker: char, label: Option<&str>) {
+    if !merged.is_empty() && !merged.ends_with(b"\n") {
+        merged.push(b'\n');
+    }
+    let marker = marker.to_string().repeat(7);
+    let line = match label {
+        Some(label) => format!("{} {}\n", marker, label),
+        None => format!("{}\n", marker),
+    };
+    merged.extend(line.as_bytes());
+}
+
+fn merge_entries(
+    name: &Path,
+    base: Option<&TreeEntry>,
+    ours: &TreeEntry,
+    theirs: &TreeEntry,
+    labels: (&str, &str),
+    config: &Config,
+) -> Result<(TreeEntry, bool)> {
+    let base_content = match base.filter(|b| is_mergeable(b)) {
+        Some(base) => read_blob(base.sha1(), config)?,
+        None => Vec::new(),
+    };
+    let (content, clean) = merge_blobs(
+        &base_content,
+        &read_blob(ours.sha1(), config)?,
+        &read_blob(theirs.sha1(), config)?,
+        labels,
+    );
+    let sha1 = GitObject::write_blob_stream(Cursor::new(&content), content.len() as u64, config)?;
+
+    let st_mode = match base {
+        Some(base) if base.st_mode() == ours.st_mode() => theirs.st_mode(),
+        _ => ours.st_mode(),
+    };
+    Ok((TreeEntry::new(st_mode, name.to_owned(), sha1), clean))
+}
+
+/// Reads a whole blob into memory.
+pub fn read_blob(sha1: &Sha1Hash, config: &Config) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    let _ = GitObject::open_blob(&config.obj_db_path, sha1)?.read_to_end(&mut content)?;
+
+    Ok(content)
+}
+
+fn same(a: Option<&TreeEntry>, b: Option<&TreeEntry>) -> bool {
+    a.map(|e| (e.st_mode(), e.sha1())) == b.map(|e| (e.st_mode(), e.sha1()))
+}
+
+fn is_mergeable(entry: &TreeEntry) -> bool {
+    !entry.is_symlink() && !entry.is_gitlink()
+}
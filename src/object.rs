@@ -1,10 +1,12 @@
 mod blob;
 mod commit;
+mod stream;
 mod tree;
 
 use std::{
     convert::TryInto,
-    fmt, fs,
+    fmt,
+    fs::{self, File},
     io::{Read, Write},
     path::Path,
 };
@@ -13,13 +15,18 @@ use bincode::{deserialize, serialize};
 use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
 use snap::{read::FrameDecoder, write::FrameEncoder};
+use tracing::debug;
 
 pub use self::{
     blob::Blob,
-    commit::Commit,
-    tree::{Tree, TreeEntry},
+    commit::{ident_date, ident_timestamp, split_ident, Commit},
+    stream::{BlobReader, ObjectStream},
+    tree::{
+        Tree, TreeEntry, DIRECTORY_MODE, EXECUTABLE_MODE, GITLINK_MODE, REGULAR_FILE_MODE,
+        SYMLINK_MODE,
+    },
 };
-use crate::{Config, Result, Sha1Hash};
+use crate::{command::tempfile, Config, GitError, Result, Sha1Hash};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum GitObject {
@@ -29,13 +36,6 @@ pub enum GitObject {
 }
 
 impl GitObject {
-    pub fn new_blob(blob_path: &Path) -> Result<Blob> {
-        let bytes = fs::read(blob_path)?;
-        let blob = Blob::new(bytes);
-
-        Ok(blob)
-    }
-
     pub fn new_tree() -> Tree {
         Tree::default()
     }
@@ -55,30 +55,134 @@ impl GitObject {
         )
     }
 
+    pub fn has_sha1_file(obj_db_path: &Path, sha1: &Sha1Hash) -> bool {
+        obj_db_path.join(sha1.sha1_file_name()).is_file()
+    }
+
+    /// Lists every object stored in the fan-out directories of the database.
+    pub fn list_sha1_files(obj_db_path: &Path) -> Result<Vec<Sha1Hash>> {
+        let mut objects = Vec::new();
+        for i in 0..256 {
+            let prefix = format!("{:02x}", i);
+            let dir = obj_db_path.join(&prefix);
+            if !dir.is_dir() {
+                continue;
+            }
+
+            for entry in fs::read_dir(dir)? {
+                let file_name = entry?.file_name();
+                let hex_str = format!("{}{}", prefix, file_name.to_string_lossy());
+                if let Ok(sha1) = hex_str.parse() {
+                    objects.push(sha1);
+                }
+            }
+        }
+
+        Ok(objects)
+    }
+
     pub fn read_sha1_file(obj_db_path: &Path, sha1: &Sha1Hash) -> Result<Self> {
+        let bytes = Self::read_sha1_bytes(obj_db_path, sha1)?;
+
+        let obj = deserialize(&bytes)?;
+        Ok(obj)
+    }
+
+    pub fn read_commit(obj_db_path: &Path, sha1: &Sha1Hash) -> Result<Commit> {
+        match Self::read_sha1_file(obj_db_path, sha1)? {
+            Self::Commit(commit_obj) => Ok(commit_obj),
+            Self::Blob(_) | Self::Tree(_) => Err(GitError::NotCommitError(sha1.clone())),
+        }
+    }
+
+    /// Reads the tree `sha1`, or the tree of the commit `sha1`.
+    pub fn read_tree_ish(obj_db_path: &Path, sha1: &Sha1Hash) -> Result<Tree> {
+        let tree_hash = match Self::read_sha1_file(obj_db_path, sha1)? {
+            Self::Tree(tree_obj) => return Ok(tree_obj),
+            Self::Commit(commit_obj) => commit_obj.tree().clone(),
+            Self::Blob(_) => return Err(GitError::NotTreeError(sha1.clone())),
+        };
+
+        match Self::read_sha1_file(obj_db_path, &tree_hash)? {
+            Self::Tree(tree_obj) => Ok(tree_obj),
+            Self::Blob(_) | Self::Commit(_) => Err(GitError::NotTreeError(tree_hash)),
+        }
+    }
+
+    pub fn read_sha1_bytes(obj_db_path: &Path, sha1: &Sha1Hash) -> Result<Vec<u8>> {
         let src_path = obj_db_path.join(sha1.sha1_file_name());
         let src = fs::File::open(src_path)?;
 
         let mut bytes = Vec::new();
         let _ = FrameDecoder::new(src).read_to_end(&mut bytes)?;
 
-        let obj = deserialize(&bytes)?;
-        Ok(obj)
+        Ok(bytes)
     }
 
-    pub fn write_sha1_file(&self, obj_db_path: &Path) -> Result<Sha1Hash> {
+    /// Computes the hash the object would be stored under without writing it.
+    pub fn hash_sha1_file(&self) -> Result<Sha1Hash> {
         let bytes = serialize(self)?;
+        let sha1 = Sha1::digest(&bytes).as_slice().try_into()?;
 
-        let sha1: Sha1Hash = Sha1::digest(&bytes).as_slice().try_into()?;
-        let dest_path = obj_db_path.join(&sha1.sha1_file_name());
+        Ok(sha1)
+    }
 
-        let dest = fs::File::create(dest_path)?;
-        FrameEncoder::new(dest).write_all(&bytes)?;
+    pub fn write_sha1_file(&self, config: &Config) -> Result<Sha1Hash> {
+        let bytes = serialize(self)?;
+        Self::write_sha1_bytes(&bytes, config)
+    }
+
+    /// Stores already serialized object bytes. Objects that are already
+    /// present are left untouched; new ones are written to a temporary file
+    /// in their fan-out directory and renamed into place.
+    pub fn write_sha1_bytes(bytes: &[u8], config: &Config) -> Result<Sha1Hash> {
+        let sha1: Sha1Hash = Sha1::digest(bytes).as_slice().try_into()?;
+        if Self::has_sha1_file(&config.obj_db_path, &sha1) {
+            debug!("{} already exists", sha1);
+            return Ok(sha1);
+        }
+
+        let dest_path = config.obj_db_path.join(sha1.sha1_file_name());
+        let fan_out_dir = dest_path.parent().unwrap_or(&config.obj_db_path);
+        let (tmp_path, tmp_file) = tempfile(fan_out_dir)?;
+
+        let mut encoder = FrameEncoder::new(tmp_file);
+        let result = encoder
+            .write_all(bytes)
+            .map_err(GitError::from)
+            .and_then(|()| encoder.into_inner().map_err(|e| e.into_error().into()))
+            .and_then(|tmp_file| finalize_object_file(&tmp_path, tmp_file, &dest_path, config));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result?;
 
         Ok(sha1)
     }
 }
 
+/// Makes a fully written temporary object read-only and renames it to its
+/// final name, so readers never observe a partially written object. With
+/// `fsync_object_files` configured, it is flushed to disk first.
+fn finalize_object_file(
+    tmp_path: &Path,
+    tmp_file: File,
+    dest_path: &Path,
+    config: &Config,
+) -> Result<()> {
+    if config.fsync_object_files {
+        tmp_file.sync_all()?;
+    }
+
+    let mut perms = tmp_file.metadata()?.permissions();
+    perms.set_readonly(true);
+    tmp_file.set_permissions(perms)?;
+    drop(tmp_file);
+
+    fs::rename(tmp_path, dest_path)?;
+    Ok(())
+}
+
 impl fmt::Display for GitObject {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
@@ -1,8 +1,9 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
 
+use chrono::{DateTime, FixedOffset};
 use serde::{Deserialize, Serialize};
 
-use crate::Sha1Hash;
+use crate::{GitError, Result, Sha1Hash};
 
 #[derive(Debug, Serialize, Deserialize)]
 
@@ -31,6 +32,48 @@ impl Commit {
             user_comment,
         }
     }
+
+    pub fn tree(&self) -> &Sha1Hash {
+        &self.sha1
+    }
+
+    pub fn parents(&self) -> &[Sha1Hash] {
+        &self.parents
+    }
+
+    pub fn author_info(&self) -> &str {
+        &self.author_info
+    }
+
+    pub fn committer_info(&self) -> &str {
+        &self.committer_info
+    }
+
+    pub fn user_comment(&self) -> &str {
+        &self.user_comment
+    }
+}
+
+/// Splits an author or committer line, `Name,,, <email> date`, into its
+/// name, email and date.
+pub fn split_ident(info: &str) -> (&str, &str, &str) {
+    let (name, rest) = info.split_once(" <").unwrap_or((info, ""));
+    let (email, date) = rest.split_once("> ").unwrap_or((rest, ""));
+    (name.trim_end_matches(','), email, date)
+}
+
+/// Parses the date of an author or committer line.
+pub fn ident_date(info: &str) -> Option<DateTime<FixedOffset>> {
+    let (_, _, date) = split_ident(info);
+    DateTime::parse_from_str(&date.replace(" UTC", " +0000"), "%Y-%m-%d %H:%M:%S%.f %z").ok()
+}
+
+/// Converts the date of an author or committer line to seconds since the
+/// epoch, or 0 when it cannot be parsed.
+pub fn ident_timestamp(info: &str) -> i64 {
+    ident_date(info)
+        .map(|date| date.timestamp())
+        .unwrap_or_default()
 }
 
 impl fmt::Display for Commit {
@@ -44,3 +87,37 @@ impl fmt::Display for Commit {
         write!(f, "\n{}", self.user_comment)
     }
 }
+
+/// Parses the text printed by the `Display` implementation.
+impl FromStr for Commit {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let malformed = || GitError::MalformedObject("commit".to_owned(), s.to_owned());
+        let (header, user_comment) = s.split_once("\n\n").ok_or_else(malformed)?;
+
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author_info = None;
+        let mut committer_info = None;
+        for line in header.lines() {
+            match line.split_once(' ').ok_or_else(malformed)? {
+                ("tree", sha1) if tree.is_none() => tree = Some(sha1.parse()?),
+                ("parent", sha1) => parents.push(sha1.parse()?),
+                ("author", info) if author_info.is_none() => author_info = Some(info.to_owned()),
+                ("committer", info) if committer_info.is_none() => {
+                    committer_info = Some(info.to_owned())
+                }
+                _ => return Err(malformed()),
+            }
+        }
+
+        Ok(Self::new(
+            tree.ok_or_else(malformed)?,
+            parents,
+            author_info.ok_or_else(malformed)?,
+            committer_info.ok_or_else(malformed)?,
+            user_comment.to_owned(),
+        ))
+    }
+}
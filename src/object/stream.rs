@@ -0,0 +1,196 @@
+use std::{
+    convert::TryInto,
+    fs::{self, File},
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    os::unix::ffi::OsStringExt,
+    path::Path,
+};
+
+use bincode::{deserialize, serialize};
+use sha1::{Digest, Sha1};
+use snap::{read::FrameDecoder, write::FrameEncoder};
+use tracing::debug;
+
+use super::{finalize_object_file, GitObject};
+use crate::{command::tempfile, Config, GitError, Result, Sha1Hash};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// bincode encodes the `GitObject` variant as a little-endian `u32` and the
+/// blob's `Vec<u8>` length as a little-endian `u64` ahead of its bytes.
+const BLOB_VARIANT: u32 = 0;
+
+/// A stored object opened for reading. Blobs are decompressed on demand,
+/// other objects are small enough to be decoded eagerly.
+#[derive(Debug)]
+pub enum ObjectStream {
+    Blob(BlobReader),
+    Other(GitObject),
+}
+
+#[derive(Debug)]
+pub struct BlobReader {
+    size: u64,
+    inner: io::Take<FrameDecoder<File>>,
+}
+
+impl BlobReader {
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl GitObject {
+    pub fn open_sha1_file(obj_db_path: &Path, sha1: &Sha1Hash) -> Result<ObjectStream> {
+        let src = File::open(obj_db_path.join(sha1.sha1_file_name()))?;
+        let mut decoder = FrameDecoder::new(src);
+
+        let mut variant = [0; 4];
+        decoder.read_exact(&mut variant)?;
+        if u32::from_le_bytes(variant) == BLOB_VARIANT {
+            let mut size = [0; 8];
+            decoder.read_exact(&mut size)?;
+            let size = u64::from_le_bytes(size);
+
+            return Ok(ObjectStream::Blob(BlobReader {
+                size,
+                inner: decoder.take(size),
+            }));
+        }
+
+        let mut bytes = variant.to_vec();
+        let _ = decoder.read_to_end(&mut bytes)?;
+        Ok(ObjectStream::Other(deserialize(&bytes)?))
+    }
+
+    /// Opens the blob `sha1` for reading, failing if it is another object.
+    pub fn open_blob(obj_db_path: &Path, sha1: &Sha1Hash) -> Result<BlobReader> {
+        match Self::open_sha1_file(obj_db_path, sha1)? {
+            ObjectStream::Blob(blob_reader) => Ok(blob_reader),
+            ObjectStream::Other(_) => {
+                Err(GitError::WrongObjectType(sha1.clone(), "blob".to_owned()))
+            }
+        }
+    }
+
+    /// Stores `size` bytes read from `reader` as a blob, hashing and
+    /// compressing them chunk by chunk so memory use stays bounded. The
+    /// content is hashed in a first pass, so a blob already present is not
+    /// compressed again and a new one is written to a temporary file in its
+    /// fan-out directory; `reader` is then rewound and must yield the same
+    /// bytes again.
+    pub fn write_blob_stream<R: Read + Seek>(
+        mut reader: R,
+        size: u64,
+        config: &Config,
+    ) -> Result<Sha1Hash> {
+        let start = reader.stream_position()?;
+        let sha1 = Self::hash_blob_stream(&mut reader, size)?;
+        if Self::has_sha1_file(&config.obj_db_path, &sha1) {
+            debug!("{} already exists", sha1);
+            return Ok(sha1);
+        }
+        let _ = reader.seek(SeekFrom::Start(start))?;
+
+        let dest_path = config.obj_db_path.join(sha1.sha1_file_name());
+        let fan_out_dir = dest_path.parent().unwrap_or(&config.obj_db_path);
+        let (tmp_path, tmp_file) = tempfile(fan_out_dir)?;
+        let result = Self::write_blob_temp(reader, size, &sha1, tmp_file)
+            .and_then(|tmp_file| finalize_object_file(&tmp_path, tmp_file, &dest_path, config));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result?;
+        debug!("streamed {} bytes into {}", size, sha1);
+
+        Ok(sha1)
+    }
+
+    /// Compresses the blob `sha1` into `tmp_file`, failing if what `reader`
+    /// yields no longer matches it.
+    fn write_blob_temp<R: Read>(
+        mut reader: R,
+        size: u64,
+        sha1: &Sha1Hash,
+        tmp_file: File,
+    ) -> Result<File> {
+        let mut encoder = FrameEncoder::new(tmp_file);
+        let mut hasher = Sha1::new();
+
+        let header = serialize(&(BLOB_VARIANT, size))?;
+        hasher.update(&header);
+        encoder.write_all(&header)?;
+
+        let mut buf = vec![0; CHUNK_SIZE];
+        let mut total = 0;
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            encoder.write_all(&buf[..n])?;
+            total += n as u64;
+        }
+        let tmp_file = encoder.into_inner().map_err(|e| e.into_error())?;
+
+        if total != size {
+            return Err(GitError::SizeChanged(size, total));
+        }
+        let rehashed: Sha1Hash = hasher.finalize().as_slice().try_into()?;
+        if rehashed != *sha1 {
+            return Err(GitError::ContentChanged(sha1.clone()));
+        }
+
+        Ok(tmp_file)
+    }
+
+    /// Computes the hash of a blob of `size` bytes read from `reader` without
+    /// storing it.
+    pub fn hash_blob_stream<R: Read>(reader: R, size: u64) -> Result<Sha1Hash> {
+        let mut hasher = Sha1::new();
+        hasher.update(&serialize(&(BLOB_VARIANT, size))?);
+
+        let total = io::copy(&mut reader.take(size + 1), &mut hasher)?;
+        if total != size {
+            return Err(GitError::SizeChanged(size, total));
+        }
+
+        let sha1 = hasher.finalize().as_slice().try_into()?;
+        Ok(sha1)
+    }
+
+    /// Computes the hash `write_blob_file` would store the file at `blob_path`
+    /// under.
+    pub fn hash_blob_file(blob_path: &Path) -> Result<Sha1Hash> {
+        if blob_path.symlink_metadata()?.file_type().is_symlink() {
+            let target = fs::read_link(blob_path)?.into_os_string().into_vec();
+            return Self::hash_blob_stream(&*target, target.len() as u64);
+        }
+
+        let file = File::open(blob_path)?;
+        let size = file.metadata()?.len();
+
+        Self::hash_blob_stream(file, size)
+    }
+
+    /// Stores the file at `blob_path` as a blob. A symlink is stored as the
+    /// path it points to rather than the content of its target.
+    pub fn write_blob_file(blob_path: &Path, config: &Config) -> Result<Sha1Hash> {
+        if blob_path.symlink_metadata()?.file_type().is_symlink() {
+            let target = fs::read_link(blob_path)?.into_os_string().into_vec();
+            return Self::write_blob_stream(Cursor::new(&target), target.len() as u64, config);
+        }
+
+        let file = File::open(blob_path)?;
+        let size = file.metadata()?.len();
+
+        Self::write_blob_stream(file, size, config)
+    }
+}
@@ -1,10 +1,23 @@
-use std::{collections::BTreeMap, fmt, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{cache::CacheEntry, Result, Sha1Hash};
+use crate::{cache::CacheEntry, worktree, GitError, Result, Sha1Hash};
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+pub const REGULAR_FILE_MODE: u32 = 0o100644;
+pub const EXECUTABLE_MODE: u32 = 0o100755;
+pub const SYMLINK_MODE: u32 = 0o120000;
+/// A commit in another repository nested in the work tree.
+pub const GITLINK_MODE: u32 = 0o160000;
+/// A directory as listed by `ls-tree`. Trees are flat and never store it.
+pub const DIRECTORY_MODE: u32 = 0o040000;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Tree {
     pub entries: BTreeMap<PathBuf, TreeEntry>,
 }
@@ -18,7 +31,61 @@ impl Tree {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Lists the entries as `<mode> <type> <hash>\t<path>` lines.
+impl fmt::Display for Tree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (path, entry) in &self.entries {
+            writeln!(
+                f,
+                "{:06o} {} {}\t{}",
+                entry.st_mode,
+                entry.entry_type(),
+                entry.sha1,
+                path.display()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses the lines printed by the `Display` implementation.
+impl FromStr for Tree {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut tree = Tree::default();
+        for line in s.lines().filter(|line| !line.is_empty()) {
+            let malformed = || GitError::MalformedObject("tree".to_owned(), line.to_owned());
+            let (header, path) = line.split_once('\t').ok_or_else(malformed)?;
+            let (st_mode, entry_type, sha1) = match header.split(' ').collect::<Vec<_>>()[..] {
+                [st_mode, entry_type, sha1] => (st_mode, entry_type, sha1),
+                _ => return Err(malformed()),
+            };
+
+            let st_mode = u32::from_str_radix(st_mode, 8).map_err(|_| malformed())?;
+            if !matches!(
+                st_mode,
+                REGULAR_FILE_MODE | EXECUTABLE_MODE | SYMLINK_MODE | GITLINK_MODE
+            ) {
+                return Err(malformed());
+            }
+
+            let path = PathBuf::from(path);
+            let entry = TreeEntry::new(st_mode, path.clone(), sha1.parse()?);
+            if entry_type != entry.entry_type() || !worktree::is_valid_path(&path) {
+                return Err(malformed());
+            }
+            let _ = tree.entries.insert(path, entry);
+        }
+        worktree::check_tree(&tree)
+            .map_err(|e| GitError::MalformedObject("tree".to_owned(), e.to_string()))?;
+
+        Ok(tree)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TreeEntry {
     st_mode: u32,
     name: PathBuf,
@@ -33,6 +100,35 @@ impl TreeEntry {
             sha1,
         }
     }
+
+    pub fn st_mode(&self) -> u32 {
+        self.st_mode
+    }
+
+    pub fn name(&self) -> &Path {
+        &self.name
+    }
+
+    pub fn sha1(&self) -> &Sha1Hash {
+        &self.sha1
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.st_mode == SYMLINK_MODE
+    }
+
+    pub fn is_gitlink(&self) -> bool {
+        self.st_mode == GITLINK_MODE
+    }
+
+    /// The type of the object the entry points at.
+    pub fn entry_type(&self) -> &'static str {
+        match self.st_mode {
+            GITLINK_MODE => "commit",
+            DIRECTORY_MODE => "tree",
+            _ => "blob",
+        }
+    }
 }
 
 impl fmt::Display for TreeEntry {
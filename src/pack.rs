@@ -0,0 +1,171 @@
+use std::{
+    convert::TryInto,
+    io::{Read, Write},
+};
+
+use bincode::deserialize;
+use sha1::{Digest, Sha1};
+use tracing::debug;
+
+use crate::{Config, GitError, GitObject, Result, Sha1Hash};
+
+const PACK_SIGNATURE: &[u8; 4] = b"PACK";
+const PACK_VERSION: u32 = 2;
+
+/// Streams `objects` as a pack: a header with the object count, each
+/// serialized object prefixed by its length as a `u64`, and a trailing SHA-1
+/// checksum.
+pub fn write_pack<W: Write>(objects: &[Sha1Hash], writer: W, config: &Config) -> Result<()> {
+    let mut writer = HashingWriter::new(writer);
+    writer.write_all(PACK_SIGNATURE)?;
+    writer.write_all(&PACK_VERSION.to_be_bytes())?;
+    let count: u32 = objects
+        .len()
+        .try_into()
+        .map_err(|_| GitError::PackError(format!("too many objects: {}", objects.len())))?;
+    writer.write_all(&count.to_be_bytes())?;
+
+    for sha1 in objects {
+        let bytes = GitObject::read_sha1_bytes(&config.obj_db_path, sha1)?;
+        writer.write_all(&(bytes.len() as u64).to_be_bytes())?;
+        writer.write_all(&bytes)?;
+    }
+
+    let (mut inner, checksum) = writer.finish();
+    inner.write_all(&checksum)?;
+    inner.flush()?;
+    debug!("wrote pack of {} objects", objects.len());
+
+    Ok(())
+}
+
+/// Reads a pack produced by `write_pack`, storing every object in the
+/// database and returning their hashes in pack order.
+pub fn read_pack<R: Read>(reader: R, config: &Config) -> Result<Vec<Sha1Hash>> {
+    read_objects(reader, Some(config))
+}
+
+/// Checks a pack produced by `write_pack` without storing its objects,
+/// returning their hashes in pack order.
+pub fn verify_pack<R: Read>(reader: R) -> Result<Vec<Sha1Hash>> {
+    read_objects(reader, None)
+}
+
+fn read_objects<R: Read>(reader: R, config: Option<&Config>) -> Result<Vec<Sha1Hash>> {
+    let mut reader = HashingReader::new(reader);
+
+    let mut signature = [0; 4];
+    reader.read_exact(&mut signature)?;
+    if &signature != PACK_SIGNATURE {
+        return Err(GitError::PackError("bad signature".to_owned()));
+    }
+    let version = read_u32(&mut reader)?;
+    if version != PACK_VERSION {
+        return Err(GitError::PackError(format!(
+            "unsupported version {}",
+            version
+        )));
+    }
+
+    // The count and lengths come from the peer, so nothing is allocated for
+    // them up front: a pack claiming more than it holds runs out of data.
+    let count = read_u32(&mut reader)?;
+    let mut objects = Vec::new();
+    for _ in 0..count {
+        let len = read_u64(&mut reader)?;
+        let mut bytes = Vec::new();
+        let read = reader.by_ref().take(len).read_to_end(&mut bytes)?;
+        if read as u64 != len {
+            return Err(GitError::PackError(format!(
+                "expected {} bytes but read {}",
+                len, read
+            )));
+        }
+
+        let _: GitObject = deserialize(&bytes)?;
+        objects.push(match config {
+            Some(config) => GitObject::write_sha1_bytes(&bytes, config)?,
+            None => Sha1::digest(&bytes).as_slice().try_into()?,
+        });
+    }
+
+    let (mut inner, expected) = reader.finish();
+    let mut checksum = [0; 20];
+    inner.read_exact(&mut checksum)?;
+    if checksum[..] != expected[..] {
+        return Err(GitError::PackError("checksum mismatch".to_owned()));
+    }
+    debug!("read pack of {} objects", objects.len());
+
+    Ok(objects)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha1,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha1::new(),
+        }
+    }
+
+    fn finish(self) -> (W, [u8; 20]) {
+        let checksum = self.hasher.finalize().as_slice().try_into().unwrap();
+        (self.inner, checksum)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha1,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha1::new(),
+        }
+    }
+
+    fn finish(self) -> (R, [u8; 20]) {
+        let checksum = self.hasher.finalize().as_slice().try_into().unwrap();
+        (self.inner, checksum)
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{refs, Config, GitError, Result, Sha1Hash};
+
+const LOGS_DIRECTORY: &str = "logs";
+
+/// One line of `.dircache/logs/<ref>`: a ref moving from `old` to `new`.
+#[derive(Clone, Debug)]
+pub struct ReflogEntry {
+    pub old: Sha1Hash,
+    pub new: Sha1Hash,
+    pub timestamp: i64,
+    pub committer: String,
+    pub message: String,
+}
+
+impl fmt::Display for ReflogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}\t{}",
+            self.old, self.new, self.timestamp, self.committer, self.message
+        )
+    }
+}
+
+impl FromStr for ReflogEntry {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let malformed = || GitError::ParseReflogError(s.to_owned());
+        let (header, message) = s.split_once('\t').ok_or_else(malformed)?;
+        let mut fields = header.splitn(4, ' ');
+        let mut next = || fields.next().ok_or_else(malformed);
+
+        Ok(Self {
+            old: next()?.parse()?,
+            new: next()?.parse()?,
+            timestamp: next()?.parse().map_err(|_| malformed())?,
+            committer: next()?.to_owned(),
+            message: message.to_owned(),
+        })
+    }
+}
+
+fn reflog_path(name: &str, config: &Config) -> PathBuf {
+    config.repo_path.join(LOGS_DIRECTORY).join(name)
+}
+
+pub fn append_reflog(
+    name: &str,
+    old: &Sha1Hash,
+    new: &Sha1Hash,
+    message: &str,
+    config: &Config,
+) -> Result<()> {
+    let entry = ReflogEntry {
+        old: old.clone(),
+        new: new.clone(),
+        timestamp: config.commit_date.timestamp(),
+        committer: format!("{} <{}>", config.committer_name, config.committer_email),
+        message: message.replace('\n', " "),
+    };
+
+    let log_path = reflog_path(name, config);
+    if let Some(parent) = log_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut log_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)?;
+    writeln!(log_file, "{}", entry)?;
+
+    Ok(())
+}
+
+/// Reads the reflog of `name`, oldest entry first.
+pub fn read_reflog(name: &str, config: &Config) -> Result<Vec<ReflogEntry>> {
+    let content = match fs::read_to_string(reflog_path(name, config)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    content.lines().map(str::parse).collect()
+}
+
+/// Rewrites the reflog of `name` keeping only the entries `keep` accepts.
+pub fn retain_reflog<F>(name: &str, mut keep: F, config: &Config) -> Result<usize>
+where
+    F: FnMut(&ReflogEntry) -> bool,
+{
+    let entries = read_reflog(name, config)?;
+    let total = entries.len();
+    let kept = entries.into_iter().filter(|e| keep(e)).collect::<Vec<_>>();
+    if kept.len() == total {
+        return Ok(0);
+    }
+
+    let log_path = reflog_path(name, config);
+    let lock_path = refs::lock_path(&log_path);
+    let mut lock_file = OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(&lock_path)?;
+    for entry in &kept {
+        writeln!(lock_file, "{}", entry)?;
+    }
+    fs::rename(&lock_path, &log_path)?;
+
+    Ok(total - kept.len())
+}
+
+pub fn delete_reflog(name: &str, config: &Config) -> Result<()> {
+    match fs::remove_file(reflog_path(name, config)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Lists the names of every ref that has a reflog.
+pub fn list_reflogs(config: &Config) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    let logs_dir = config.repo_path.join(LOGS_DIRECTORY);
+    if logs_dir.is_dir() {
+        collect_reflogs(&logs_dir, None, &mut names)?;
+    }
+    names.sort();
+
+    Ok(names)
+}
+
+fn collect_reflogs(dir: &Path, prefix: Option<&str>, names: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let name = match prefix {
+            Some(prefix) => format!("{}/{}", prefix, file_name),
+            None => file_name.clone(),
+        };
+
+        if entry.file_type()?.is_dir() {
+            collect_reflogs(&entry.path(), Some(&name), names)?;
+        } else if !file_name.ends_with(".lock") && refs::check_ref_name(&name).is_ok() {
+            names.push(name);
+        }
+    }
+
+    Ok(())
+}
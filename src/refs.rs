@@ -0,0 +1,334 @@
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use tracing::debug;
+
+use crate::{reflog, Config, GitError, Result, Sha1Hash};
+
+pub const HEAD: &str = "HEAD";
+pub const REFS_PREFIX: &str = "refs/";
+pub const HEADS_PREFIX: &str = "refs/heads/";
+pub const DEFAULT_BRANCH: &str = "refs/heads/master";
+
+const SYMREF_PREFIX: &str = "ref: ";
+
+pub fn check_ref_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.ends_with('/')
+        && !name.contains("..")
+        && (name == HEAD || name.starts_with(REFS_PREFIX))
+        && Path::new(name).components().all(|c| match c {
+            Component::Normal(part) => !part.to_string_lossy().ends_with(".lock"),
+            _ => false,
+        });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(GitError::InvalidRefName(name.to_owned()))
+    }
+}
+
+/// Expands a short branch name such as `master` to `refs/heads/master`.
+pub fn expand_ref_name(name: &str) -> String {
+    if name == HEAD || name.starts_with(REFS_PREFIX) {
+        name.to_owned()
+    } else {
+        format!("{}{}", HEADS_PREFIX, name)
+    }
+}
+
+/// Returns the target of `name` if it is a symbolic ref such as `HEAD`.
+pub fn read_symref(name: &str, config: &Config) -> Result<Option<String>> {
+    check_ref_name(name)?;
+    let content = match fs::read_to_string(config.repo_path.join(name)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(content
+        .trim_end()
+        .strip_prefix(SYMREF_PREFIX)
+        .map(str::to_owned))
+}
+
+/// Follows symbolic refs and returns the name of the ref that holds the hash.
+pub fn resolve_ref(name: &str, config: &Config) -> Result<String> {
+    let mut name = name.to_owned();
+    while let Some(target) = read_symref(&name, config)? {
+        name = target;
+    }
+
+    Ok(name)
+}
+
+pub fn read_ref(name: &str, config: &Config) -> Result<Option<Sha1Hash>> {
+    let name = resolve_ref(name, config)?;
+    let content = match fs::read_to_string(config.repo_path.join(&name)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let sha1 = content.trim_end().parse()?;
+    Ok(Some(sha1))
+}
+
+/// Points `name` at `sha1`, recording `message` in its reflog.
+pub fn write_ref(name: &str, sha1: &Sha1Hash, message: &str, config: &Config) -> Result<()> {
+    let name = resolve_ref(name, config)?;
+    let old = read_ref(&name, config)?.unwrap_or_default();
+    write_ref_file(&name, format!("{}\n", sha1), config)?;
+    log_ref_update(&name, &old, sha1, message, config)?;
+    debug!("{} -> {}", name, sha1);
+
+    Ok(())
+}
+
+/// Points `name` at `sha1` without recording it in the reflog, for callers
+/// that rewrite the reflog themselves.
+pub fn set_ref(name: &str, sha1: &Sha1Hash, config: &Config) -> Result<()> {
+    let name = resolve_ref(name, config)?;
+    write_ref_file(&name, format!("{}\n", sha1), config)
+}
+
+/// Points `HEAD` directly at `sha1` instead of at a branch.
+pub fn detach_head(sha1: &Sha1Hash, message: &str, config: &Config) -> Result<()> {
+    let old = read_ref(HEAD, config)?.unwrap_or_default();
+    write_ref_file(HEAD, format!("{}\n", sha1), config)?;
+    reflog::append_reflog(HEAD, &old, sha1, message, config)
+}
+
+pub fn write_symref(name: &str, target: &str, config: &Config) -> Result<()> {
+    check_ref_name(target)?;
+    write_ref_file(name, format!("{}{}\n", SYMREF_PREFIX, target), config)
+}
+
+pub fn delete_ref(name: &str, config: &Config) -> Result<()> {
+    let name = resolve_ref(name, config)?;
+    if let Err(e) = fs::remove_file(config.repo_path.join(&name)) {
+        if e.kind() != ErrorKind::NotFound {
+            return Err(e.into());
+        }
+    }
+
+    reflog::delete_reflog(&name, config)
+}
+
+/// Lists every ref under `refs/`, sorted by name.
+pub fn list_refs(config: &Config) -> Result<Vec<(String, Sha1Hash)>> {
+    let mut refs = Vec::new();
+    let refs_dir = config.repo_path.join(REFS_PREFIX);
+    if refs_dir.is_dir() {
+        collect_refs(
+            &refs_dir,
+            REFS_PREFIX.trim_end_matches('/'),
+            &mut refs,
+            config,
+        )?;
+    }
+    refs.sort();
+
+    Ok(refs)
+}
+
+fn collect_refs(
+    dir: &Path,
+    prefix: &str,
+    refs: &mut Vec<(String, Sha1Hash)>,
+    config: &Config,
+) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let name = format!("{}/{}", prefix, file_name);
+
+        if entry.file_type()?.is_dir() {
+            collect_refs(&entry.path(), &name, refs, config)?;
+        } else if !file_name.ends_with(".lock") {
+            if let Some(sha1) = read_ref(&name, config)? {
+                refs.push((name, sha1));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A `[+]<src>:<dst>` push refspec. An empty `src` deletes `dst`, and a
+/// leading `+` allows non-fast-forward updates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RefSpec {
+    pub src: Option<String>,
+    pub dst: String,
+    pub force: bool,
+}
+
+impl FromStr for RefSpec {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (force, spec) = match s.strip_prefix('+') {
+            Some(spec) => (true, spec),
+            None => (false, s),
+        };
+        let (src, dst) = spec.split_once(':').unwrap_or((spec, spec));
+        if dst.is_empty() {
+            return Err(GitError::InvalidRefName(s.to_owned()));
+        }
+
+        let dst = expand_ref_name(dst);
+        check_ref_name(&dst)?;
+        let src = Some(src).filter(|src| !src.is_empty()).map(expand_ref_name);
+
+        Ok(Self { src, dst, force })
+    }
+}
+
+/// A set of ref updates applied all-or-nothing. Every ref is locked with a
+/// `<ref>.lock` file, checked against its expected old value and only then
+/// renamed into place.
+#[derive(Debug)]
+pub struct RefTransaction {
+    message: String,
+    updates: Vec<(String, Sha1Hash, Sha1Hash)>,
+}
+
+#[derive(Debug)]
+struct LockedRef {
+    name: String,
+    ref_path: PathBuf,
+    lock_path: PathBuf,
+    old: Sha1Hash,
+    new: Sha1Hash,
+}
+
+impl RefTransaction {
+    /// Starts a transaction whose updates are recorded in the reflogs with
+    /// `message`.
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_owned(),
+            updates: Vec::new(),
+        }
+    }
+
+    /// Queues moving `name` from `old` to `new`; a null `new` deletes it
+    /// and a null `old` requires it not to exist.
+    pub fn update(&mut self, name: &str, old: Sha1Hash, new: Sha1Hash) {
+        self.updates.push((name.to_owned(), old, new));
+    }
+
+    pub fn commit(self, config: &Config) -> Result<()> {
+        let mut locked = Vec::new();
+        let result = self.lock_all(&mut locked, config);
+        if result.is_err() {
+            for locked_ref in &locked {
+                let _ = fs::remove_file(&locked_ref.lock_path);
+            }
+        }
+
+        result
+    }
+
+    fn lock_all(&self, locked: &mut Vec<LockedRef>, config: &Config) -> Result<()> {
+        for (name, old, new) in &self.updates {
+            let name = resolve_ref(name, config)?;
+            check_ref_name(&name)?;
+            let ref_path = config.repo_path.join(&name);
+            if let Some(parent) = ref_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let lock_path = lock_path(&ref_path);
+            let mut lock_file = fs::OpenOptions::new()
+                .create_new(true)
+                .write(true)
+                .open(&lock_path)?;
+            locked.push(LockedRef {
+                name: name.clone(),
+                ref_path,
+                lock_path,
+                old: old.clone(),
+                new: new.clone(),
+            });
+
+            let current = read_ref(&name, config)?.unwrap_or_default();
+            if &current != old {
+                return Err(GitError::StaleRef(name));
+            }
+            if !new.is_null() {
+                writeln!(lock_file, "{}", new)?;
+            }
+        }
+
+        for locked_ref in locked.iter() {
+            if locked_ref.new.is_null() {
+                match fs::remove_file(&locked_ref.ref_path) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                    _ => fs::remove_file(&locked_ref.lock_path)?,
+                }
+                reflog::delete_reflog(&locked_ref.name, config)?;
+            } else {
+                fs::rename(&locked_ref.lock_path, &locked_ref.ref_path)?;
+                log_ref_update(
+                    &locked_ref.name,
+                    &locked_ref.old,
+                    &locked_ref.new,
+                    &self.message,
+                    config,
+                )?;
+            }
+            debug!("{} -> {}", locked_ref.name, locked_ref.new);
+        }
+        locked.clear();
+
+        Ok(())
+    }
+}
+
+/// Appends to the reflog of `name`, and to that of `HEAD` when it points at
+/// `name`.
+fn log_ref_update(
+    name: &str,
+    old: &Sha1Hash,
+    new: &Sha1Hash,
+    message: &str,
+    config: &Config,
+) -> Result<()> {
+    reflog::append_reflog(name, old, new, message, config)?;
+    if name != HEAD && resolve_ref(HEAD, config)? == name {
+        reflog::append_reflog(HEAD, old, new, message, config)?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn lock_path(path: &Path) -> PathBuf {
+    let mut lock_name = path.file_name().unwrap_or_default().to_owned();
+    lock_name.push(".lock");
+    path.with_file_name(lock_name)
+}
+
+fn write_ref_file(name: &str, content: String, config: &Config) -> Result<()> {
+    check_ref_name(name)?;
+    let ref_path = config.repo_path.join(name);
+    if let Some(parent) = ref_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let lock_path = lock_path(&ref_path);
+    let mut lock_file = fs::OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(&lock_path)?;
+    lock_file.write_all(content.as_bytes())?;
+    fs::rename(&lock_path, &ref_path)?;
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use tracing::debug;
+
+use crate::{reflog, refs, Config, GitError, Result, Sha1Hash};
+
+/// Resolves a revision to an object hash. Accepts full hashes, ref names
+/// (`HEAD`, `master`, `refs/heads/master`) and `<ref>@{N}`, the value the
+/// ref had `N` updates ago according to its reflog (`@{N}` means `HEAD`).
+pub fn parse_revision(rev: &str, config: &Config) -> Result<Sha1Hash> {
+    let unknown = || GitError::UnknownRevision(rev.to_owned());
+    debug!("parsing revision {}", rev);
+
+    if let Some((base, rest)) = rev.split_once("@{") {
+        let n = rest
+            .strip_suffix('}')
+            .and_then(|n| n.parse::<usize>().ok())
+            .ok_or_else(unknown)?;
+        let name = match base {
+            "" => refs::HEAD.to_owned(),
+            base => resolve_ref_name(base, config)?.ok_or_else(unknown)?,
+        };
+
+        let entries = reflog::read_reflog(&name, config)?;
+        return entries
+            .iter()
+            .rev()
+            .nth(n)
+            .map(|entry| entry.new.clone())
+            .ok_or_else(unknown);
+    }
+
+    if rev.len() == 40 {
+        if let Ok(sha1) = rev.parse() {
+            return Ok(sha1);
+        }
+    }
+
+    match resolve_ref_name(rev, config)? {
+        Some(name) => refs::read_ref(&name, config)?.ok_or_else(unknown),
+        None => Err(unknown()),
+    }
+}
+
+/// Finds the full name of an existing ref given its full or short name.
+pub fn resolve_ref_name(name: &str, config: &Config) -> Result<Option<String>> {
+    let candidates = [
+        name.to_owned(),
+        format!("{}{}", refs::REFS_PREFIX, name),
+        format!("{}{}", refs::HEADS_PREFIX, name),
+    ];
+
+    for candidate in candidates.iter() {
+        if refs::check_ref_name(candidate).is_ok() && refs::read_ref(candidate, config)?.is_some() {
+            return Ok(Some(candidate.clone()));
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,143 @@
+use std::{
+    io::{Read, Write},
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    str,
+};
+
+use tracing::debug;
+
+use crate::{refs, Config, GitError, Result, Sha1Hash};
+
+const FLUSH_PKT: &[u8; 4] = b"0000";
+const MAX_PKT_LEN: usize = 0xfff0;
+
+pub fn write_pkt_line<W: Write>(writer: &mut W, line: &str) -> Result<()> {
+    let len = line.len() + 5;
+    if len > MAX_PKT_LEN {
+        return Err(GitError::ProtocolError(format!("line too long: {}", len)));
+    }
+
+    writeln!(writer, "{:04x}{}", len, line)?;
+    Ok(())
+}
+
+pub fn write_flush<W: Write>(writer: &mut W) -> Result<()> {
+    writer.write_all(FLUSH_PKT)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads one pkt-line, returning `None` for a flush packet.
+pub fn read_pkt_line<R: Read>(reader: &mut R) -> Result<Option<String>> {
+    let mut len_buf = [0; 4];
+    reader.read_exact(&mut len_buf)?;
+    if &len_buf == FLUSH_PKT {
+        return Ok(None);
+    }
+
+    let len = str::from_utf8(&len_buf)
+        .ok()
+        .and_then(|s| usize::from_str_radix(s, 16).ok())
+        .filter(|&len| len > 4)
+        .ok_or_else(|| GitError::ProtocolError(format!("bad pkt-line length {:?}", len_buf)))?;
+
+    let mut line = vec![0; len - 4];
+    reader.read_exact(&mut line)?;
+    if line.last() == Some(&b'\n') {
+        let _ = line.pop();
+    }
+
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|e| GitError::ProtocolError(e.to_string()))
+}
+
+pub fn write_ref_advertisement<W: Write>(writer: &mut W, config: &Config) -> Result<()> {
+    if let Some(sha1) = refs::read_ref(refs::HEAD, config)? {
+        write_pkt_line(writer, &format!("{} {}", sha1, refs::HEAD))?;
+    }
+    for (name, sha1) in refs::list_refs(config)? {
+        write_pkt_line(writer, &format!("{} {}", sha1, name))?;
+    }
+
+    write_flush(writer)
+}
+
+pub fn read_ref_advertisement<R: Read>(reader: &mut R) -> Result<Vec<(String, Sha1Hash)>> {
+    let mut refs = Vec::new();
+    while let Some(line) = read_pkt_line(reader)? {
+        let (sha1, name) = split_line(&line)?;
+        refs.push((name.to_owned(), sha1.parse()?));
+    }
+    debug!(?refs);
+
+    Ok(refs)
+}
+
+/// Parses a `<keyword> <hash>` line such as `want <hash>`.
+pub fn parse_hash_line(line: &str, keyword: &str) -> Result<Sha1Hash> {
+    match split_line(line)? {
+        (word, sha1) if word == keyword => sha1.parse(),
+        _ => Err(GitError::ProtocolError(format!(
+            "expected {}, got {:?}",
+            keyword, line
+        ))),
+    }
+}
+
+pub fn split_line(line: &str) -> Result<(&str, &str)> {
+    line.split_once(' ')
+        .ok_or_else(|| GitError::ProtocolError(format!("malformed line {:?}", line)))
+}
+
+/// A child process speaking one side of the protocol over its stdio.
+#[derive(Debug)]
+pub struct Connection {
+    child: Child,
+    pub reader: ChildStdout,
+    pub writer: ChildStdin,
+}
+
+impl Connection {
+    pub fn spawn(program: &Path, service: &str, remote: &Path) -> Result<Self> {
+        let mut child = Command::new(program)
+            .arg(service)
+            .arg(remote)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let reader = child
+            .stdout
+            .take()
+            .expect("Child process stdout has not been captured!");
+        let writer = child
+            .stdin
+            .take()
+            .expect("Child process stdin has not been captured!");
+
+        Ok(Self {
+            child,
+            reader,
+            writer,
+        })
+    }
+
+    pub fn finish(self) -> Result<()> {
+        let Self {
+            mut child, writer, ..
+        } = self;
+        drop(writer);
+
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(GitError::ProtocolError(format!(
+                "remote exited with {}",
+                status
+            )));
+        }
+
+        Ok(())
+    }
+}
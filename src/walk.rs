@@ -0,0 +1,91 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    slice,
+};
+
+use crate::{Config, GitObject, ObjectStream, Result, Sha1Hash};
+
+/// Lists the commits reachable from `tips`, including the tips themselves.
+pub fn reachable_commits(tips: &[Sha1Hash], config: &Config) -> Result<Vec<Sha1Hash>> {
+    let mut seen = HashSet::new();
+    let mut commits = Vec::new();
+    let mut queue = tips.iter().cloned().collect::<VecDeque<_>>();
+
+    while let Some(sha1) = queue.pop_front() {
+        if !seen.insert(sha1.clone()) {
+            continue;
+        }
+        if let GitObject::Commit(commit_obj) =
+            GitObject::read_sha1_file(&config.obj_db_path, &sha1)?
+        {
+            queue.extend(commit_obj.parents().iter().cloned());
+            commits.push(sha1);
+        }
+    }
+
+    Ok(commits)
+}
+
+/// Lists every object reachable from `include` that is not reachable from
+/// `exclude`. Excluded hashes missing from the database are ignored.
+pub fn reachable_objects(
+    include: &[Sha1Hash],
+    exclude: &[Sha1Hash],
+    config: &Config,
+) -> Result<Vec<Sha1Hash>> {
+    let mut seen = HashSet::new();
+    let exclude = exclude
+        .iter()
+        .filter(|sha1| GitObject::has_sha1_file(&config.obj_db_path, sha1))
+        .cloned()
+        .collect::<Vec<_>>();
+    let _ = walk_objects(&exclude, &mut seen, config)?;
+
+    walk_objects(include, &mut seen, config)
+}
+
+fn walk_objects(
+    tips: &[Sha1Hash],
+    seen: &mut HashSet<Sha1Hash>,
+    config: &Config,
+) -> Result<Vec<Sha1Hash>> {
+    let mut objects = Vec::new();
+    let mut queue = tips.iter().cloned().collect::<VecDeque<_>>();
+
+    while let Some(sha1) = queue.pop_front() {
+        if !seen.insert(sha1.clone()) {
+            continue;
+        }
+
+        // Blobs lead nowhere, so their content is never read.
+        match GitObject::open_sha1_file(&config.obj_db_path, &sha1)? {
+            ObjectStream::Blob(_) | ObjectStream::Other(GitObject::Blob(_)) => {}
+            ObjectStream::Other(GitObject::Tree(tree_obj)) => {
+                queue.extend(
+                    tree_obj
+                        .entries
+                        .values()
+                        .filter(|entry| !entry.is_gitlink())
+                        .map(|entry| entry.sha1().clone()),
+                );
+            }
+            ObjectStream::Other(GitObject::Commit(commit_obj)) => {
+                queue.push_back(commit_obj.tree().clone());
+                queue.extend(commit_obj.parents().iter().cloned());
+            }
+        }
+        objects.push(sha1);
+    }
+
+    Ok(objects)
+}
+
+/// Whether moving a ref from `old` to `new` is a fast-forward, i.e. `old`
+/// is known locally and reachable from `new`.
+pub fn is_fast_forward(old: &Sha1Hash, new: &Sha1Hash, config: &Config) -> Result<bool> {
+    if !GitObject::has_sha1_file(&config.obj_db_path, old) {
+        return Ok(false);
+    }
+
+    Ok(reachable_commits(slice::from_ref(new), config)?.contains(old))
+}
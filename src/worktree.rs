@@ -0,0 +1,253 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File, Permissions},
+    io::{self, ErrorKind, Read},
+    os::unix::{ffi::OsStringExt, fs::PermissionsExt},
+    path::{Component, Path, PathBuf},
+};
+
+use tracing::debug;
+
+use crate::{
+    cache::cache_mode, config::REPO_DIRECTORY, refs, Cache, CacheEntry, CacheLock, Config,
+    GitError, GitObject, Result, Sha1Hash, Tree, EXECUTABLE_MODE, GITLINK_MODE, SYMLINK_MODE,
+};
+
+/// Whether `path` may name a file in the work tree: relative, without `.`
+/// or `..` components, and outside the repository directory.
+pub fn is_valid_path(path: &Path) -> bool {
+    !path.as_os_str().is_empty()
+        && path
+            .components()
+            .all(|c| matches!(c, Component::Normal(name) if name != REPO_DIRECTORY))
+}
+
+/// Fails unless every path of `names` is valid, so objects from elsewhere
+/// cannot write outside the work tree.
+pub fn check_paths<'a>(mut names: impl Iterator<Item = &'a PathBuf>) -> Result<()> {
+    match names.find(|name| !is_valid_path(name)) {
+        Some(name) => Err(GitError::InvalidPath(name.clone())),
+        None => Ok(()),
+    }
+}
+
+/// Fails if a directory above `name` is a symlink according to `mode_of`,
+/// which gives the mode of a path in the same tree, so writing `name` would
+/// go through the link.
+pub fn check_symlink_ancestors(name: &Path, mode_of: impl Fn(&Path) -> Option<u32>) -> Result<()> {
+    match name
+        .ancestors()
+        .skip(1)
+        .take_while(|parent| !parent.as_os_str().is_empty())
+        .find(|parent| mode_of(parent) == Some(SYMLINK_MODE))
+    {
+        Some(_) => Err(GitError::BeyondSymlink(name.to_owned())),
+        None => Ok(()),
+    }
+}
+
+/// Fails unless every entry of `tree` may be checked out: its path is valid
+/// and no other entry is a symlink above it.
+pub fn check_tree(tree: &Tree) -> Result<()> {
+    check_paths(tree.entries.keys())?;
+    for name in tree.entries.keys() {
+        check_symlink_ancestors(name, |parent| {
+            tree.entries.get(parent).map(|entry| entry.st_mode())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Writes the blob `sha1` to `path` in the work tree, creating a symlink or
+/// setting the executable bit according to `mode`. A gitlink only creates
+/// the directory of the nested repository. Fails if a directory above
+/// `path` in the work tree is a symlink.
+pub fn checkout_file(path: &Path, mode: u32, sha1: &Sha1Hash, config: &Config) -> Result<()> {
+    let name = path.strip_prefix(&config.work_dir).unwrap_or(path);
+    check_symlink_ancestors(name, |parent| {
+        config
+            .work_dir
+            .join(parent)
+            .symlink_metadata()
+            .ok()
+            .filter(|meta| meta.file_type().is_symlink())
+            .map(|_| SYMLINK_MODE)
+    })?;
+
+    if mode == GITLINK_MODE {
+        fs::create_dir_all(path)?;
+        return Ok(());
+    }
+
+    let mut blob_reader = GitObject::open_blob(&config.obj_db_path, sha1)?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    remove_file(path)?;
+
+    if mode == SYMLINK_MODE {
+        let mut target = Vec::new();
+        let _ = blob_reader.read_to_end(&mut target)?;
+        std::os::unix::fs::symlink(OsString::from_vec(target), path)?;
+    } else {
+        let mut file = File::create(path)?;
+        let _ = io::copy(&mut blob_reader, &mut file)?;
+        let perm = if mode == EXECUTABLE_MODE {
+            0o755
+        } else {
+            0o644
+        };
+        file.set_permissions(Permissions::from_mode(perm))?;
+    }
+    debug!("checked out {:?} ({:o} {})", path, mode, sha1);
+
+    Ok(())
+}
+
+/// Removes a file or symlink from the work tree, ignoring missing paths.
+pub fn remove_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Makes the work tree and index match the tree `tree_hash`, deleting files
+/// the index tracks but the tree lacks. Local changes are overwritten.
+pub fn checkout_tree(tree_hash: &Sha1Hash, config: &Config) -> Result<()> {
+    let tree_obj = match GitObject::read_sha1_file(&config.obj_db_path, tree_hash)? {
+        GitObject::Tree(tree_obj) => tree_obj,
+        GitObject::Blob(_) | GitObject::Commit(_) => {
+            return Err(GitError::NotTreeError(tree_hash.clone()))
+        }
+    };
+    check_tree(&tree_obj)?;
+
+    let lock = CacheLock::acquire(&config.cache_path)?;
+    let old_cache = Cache::read_cache(&config.cache_path)?;
+    for (name, entry) in &old_cache.entries {
+        if entry.st_mode != GITLINK_MODE && !tree_obj.entries.contains_key(name) {
+            remove_file(&config.work_dir.join(name))?;
+        }
+    }
+
+    let mut cache = Cache::default();
+    for (name, entry) in tree_obj.entries {
+        let path = config.work_dir.join(&name);
+        checkout_file(&path, entry.st_mode(), entry.sha1(), config)?;
+        cache.insert_in(&config.work_dir, name, entry.sha1().clone())?;
+    }
+
+    lock.commit(&cache)
+}
+
+/// Returns the `HEAD` commit of the repository nested at `path`, if any.
+pub fn gitlink_head(path: &Path) -> Result<Option<Sha1Hash>> {
+    if !path.join(REPO_DIRECTORY).is_dir() {
+        return Ok(None);
+    }
+
+    let config = Config::with_work_dir(path)?;
+    refs::read_ref(refs::HEAD, &config)
+}
+
+/// Moves the work tree and cache from the tree `from` to the tree `to`,
+/// touching only the paths whose entries differ between them. Local changes
+/// to other paths are carried over, and so are the cache entries of the
+/// `unstaged` paths that remain in `to`. Fails without changing anything if
+/// a path to update has local changes, staged or not, or is an untracked
+/// file.
+pub fn switch_tree(from: &Tree, to: &Tree, unstaged: &[PathBuf], config: &Config) -> Result<()> {
+    check_tree(to)?;
+    let lock = CacheLock::acquire(&config.cache_path)?;
+    let mut cache = Cache::read_cache(&config.cache_path)?;
+
+    let mut changed = from
+        .entries
+        .keys()
+        .chain(to.entries.keys())
+        .filter(|name| {
+            let old = from.entries.get(*name).map(|e| (e.st_mode(), e.sha1()));
+            let new = to.entries.get(*name).map(|e| (e.st_mode(), e.sha1()));
+            old != new
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    changed.sort();
+    changed.dedup();
+
+    let mut conflicts = Vec::new();
+    for name in &changed {
+        let old = from
+            .entries
+            .get(name)
+            .map(|e| (e.st_mode(), e.sha1().clone()));
+        let new = to
+            .entries
+            .get(name)
+            .map(|e| (e.st_mode(), e.sha1().clone()));
+        let cache_entry = cache.entries.get(name);
+        let staged = cache_entry.map(|e| (e.st_mode, e.sha1.clone()));
+        let work = work_state(&config.work_dir.join(name), cache_entry)?;
+
+        let clean = staged == old && work == old;
+        let done = work == new && (staged == old || staged == new);
+        if !clean && !done {
+            conflicts.push(name.clone());
+        }
+    }
+    if !conflicts.is_empty() {
+        return Err(GitError::LocalChanges(conflicts));
+    }
+
+    for name in changed {
+        let path = config.work_dir.join(&name);
+        match to.entries.get(&name) {
+            Some(entry) => {
+                checkout_file(&path, entry.st_mode(), entry.sha1(), config)?;
+                if !unstaged.contains(&name) {
+                    cache.insert_in(&config.work_dir, name, entry.sha1().clone())?;
+                }
+            }
+            None => {
+                if from.entries.get(&name).is_some_and(|e| !e.is_gitlink()) {
+                    remove_file(&path)?;
+                }
+                let _ = cache.entries.remove(&name);
+            }
+        }
+    }
+
+    lock.commit(&cache)
+}
+
+/// The mode and hash of what is at `path` in the work tree, trusting the
+/// cache entry when the stat data matches.
+fn work_state(path: &Path, cache_entry: Option<&CacheEntry>) -> Result<Option<(u32, Sha1Hash)>> {
+    let meta = match path.symlink_metadata() {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mode = cache_mode(&meta);
+
+    match cache_entry {
+        // Switching never touches the content of nested repositories.
+        Some(entry) if mode == GITLINK_MODE && entry.st_mode == GITLINK_MODE => {
+            return Ok(Some((entry.st_mode, entry.sha1.clone())))
+        }
+        Some(entry) if entry.match_stat(meta.clone()) == 0 => {
+            return Ok(Some((entry.st_mode, entry.sha1.clone())))
+        }
+        _ => {}
+    }
+
+    let sha1 = if mode == GITLINK_MODE {
+        gitlink_head(path)?.unwrap_or_default()
+    } else {
+        GitObject::hash_blob_file(path)?
+    };
+    Ok(Some((mode, sha1)))
+}